@@ -7,8 +7,26 @@ const material_track = new THREE.LineBasicMaterial( { color: 0xff0000, linewidth
 const material_double = new THREE.LineBasicMaterial( { color: 0xffa500, linewidth: 3 } );
 const material_station = new THREE.LineBasicMaterial( { color: 0x0000ff, linewidth: 5 } );
 const material2 = new THREE.LineBasicMaterial( { color: 0x000000, linewidth: 1 } );
+const material_solution = new THREE.LineBasicMaterial( { color: 0x008000, linewidth: 1 } );
+const material_conflict = new THREE.LineBasicMaterial( { color: 0xff00ff, linewidth: 5 } );
+const time_scale = 10.0 / 3600.0;
+
+// Files can be chosen with URL parameters, e.g. `?solution=sol.json`.
+const params = new URLSearchParams(window.location.search);
+const infrastructure_file = params.get("infrastructure") ?? "i1.json";
+const timetable_file = params.get("timetable") ?? "tt1.json";
+const solution_file = params.get("solution");
+
+Promise.all([
+  fetch(infrastructure_file).then(r => r.json()),
+  fetch(timetable_file).then(r => r.json()),
+  solution_file ? fetch(solution_file).then(r => r.json()) : null,
+]).then(([infrastructure, timetable, solution]) => {
+  add_infrastructure(infrastructure);
+  add_timetable(infrastructure, timetable, solution);
+  add_animation(infrastructure, timetable, solution);
+});
 
-fetch("i1.json").then(r => r.json()).then(add_infrastructure);
 function add_infrastructure(infrastructure) {
 
   const points = [];
@@ -21,9 +39,9 @@ function add_infrastructure(infrastructure) {
   const station_index = [];
   for(const res of infrastructure.resources) {
 
-    const index = 
-    res.restype.type === "SingleTrack" ? singletrack_index : 
-    res.restype.type === "DoubleTrack" ? doubletrack_index : 
+    const index =
+    res.restype.type === "SingleTrack" ? singletrack_index :
+    res.restype.type === "DoubleTrack" ? doubletrack_index :
     res.restype.type === "Station" ? station_index :
     undefined;
 
@@ -41,89 +59,261 @@ function add_infrastructure(infrastructure) {
 
     const infrastructureGeometry = new THREE.BufferGeometry();
     const positionAttribute = new THREE.Float32BufferAttribute( points, 3 );
-    const indexAttribute = new THREE.Uint16BufferAttribute(index, 1 ); 
+    const indexAttribute = new THREE.Uint16BufferAttribute(index, 1 );
     infrastructureGeometry.setAttribute( 'position', positionAttribute );
     infrastructureGeometry.setIndex( indexAttribute );
-    
+
     const line = new THREE.Line( infrastructureGeometry, material );
     scene.add( line );
   }
+}
 
+// Node numbering of the disjunctive graph (see `disjunctive::train_nodes`):
+// one node per operation, followed by one node for the final exit.
+function train_nodes(timetable) {
+  let next_node = 0;
+  return timetable.trains.map(train => {
+    const nodes = [];
+    for(let i = 0; i <= train.operations.length; i += 1) {
+      nodes.push(next_node);
+      next_node += 1;
+    }
+    return nodes;
+  });
+}
 
+// Entry and exit time of every operation, from the solution if there is one
+// and from the planned timetable otherwise.
+function operation_times(timetable, solution) {
+  const nodes = train_nodes(timetable);
+  return timetable.trains.map((train, train_idx) => train.operations.map((op, op_idx) => {
+    if (solution) {
+      return [solution.positions[nodes[train_idx][op_idx]], solution.positions[nodes[train_idx][op_idx + 1]]];
+    }
+    const next = train.operations[op_idx + 1];
+    return [op.time, next ? next.time : op.time + op.min_duration];
+  }));
+}
 
-  fetch("tt1.json").then(r => r.json()).then(add_timetable);
-  function add_timetable(timetable) {
+function add_timetable(infrastructure, timetable, solution) {
 
-    console.log("TT");
+  console.log("TT");
 
-    
+  const paths = [[operation_times(timetable, null), material2]];
+  if (solution) {
+    paths.push([operation_times(timetable, solution), material_solution]);
+  }
 
-    const time_scale = 10.0 / 3600.0;
+  for(const [times, material] of paths) {
     const points = [];
     const index = [];
-    for(const train of timetable.trains) {
+    for(const [train_idx, train] of timetable.trains.entries()) {
       for(const [op_idx,op] of train.operations.entries()) {
+        const [time_in, time_out] = times[train_idx][op_idx];
         const res = infrastructure.resources[op.resource];
         const node_lo = infrastructure.nodes[res.node_lo];
         const node_hi = infrastructure.nodes[res.node_hi];
         const pt = op.forward ? node_lo.location : node_hi.location;
         index.push(points.length/3);
-        points.push(pt.x, pt.y, op.time *time_scale);
+        points.push(pt.x, pt.y, time_in *time_scale);
         if (op_idx + 1 == train.operations.length) {
           const pt = (!op.forward) ? node_lo.location : node_hi.location;
           index.push(points.length/3);
-          points.push(pt.x, pt.y, (op.time + op.min_duration) *time_scale);
+          points.push(pt.x, pt.y, time_out *time_scale);
         }
       }
       index.push(65535);
     }
-    console.log(points);
-    console.log(index);
 
     const geometry = new THREE.BufferGeometry();
     const positionAttribute = new THREE.Float32BufferAttribute( points, 3 );
-    const indexAttribute = new THREE.Uint16BufferAttribute(index, 1 ); 
+    const indexAttribute = new THREE.Uint16BufferAttribute(index, 1 );
     geometry.setAttribute( 'position', positionAttribute );
     geometry.setIndex( indexAttribute );
-    
-    const line = new THREE.Line( geometry, material2 );
+
+    const line = new THREE.Line( geometry, material );
     scene.add( line );
+  }
+
+  const minTime = Math.min(...timetable.trains.flatMap(t => t.operations).map(op => op.time));
+  const maxTime = Math.max(...timetable.trains.flatMap(t => t.operations).map(op => op.time));
+  const planeMaterial = new THREE.MeshBasicMaterial({color: "white", side: THREE.DoubleSide});
+  planeMaterial.transparent = true;
+  planeMaterial.opacity = 0.4;
+  for(const res of infrastructure.resources) {
+    let x1 = infrastructure.nodes[res.node_lo].location.x;
+    let y1 = infrastructure.nodes[res.node_lo].location.y;
+    let z1 = minTime * time_scale;
+    let x2 = infrastructure.nodes[res.node_hi].location.x;
+    let y2 = infrastructure.nodes[res.node_hi].location.y;
+    let z2 = maxTime * time_scale;
 
-    // for(let i = 0; i < 5; i += 1) {
-    //   const l = new THREE.Line( infrastructureGeometry, material1 );
-    //   l.translateZ(i);
-    //   scene.add( l );
-    // }
-
-    const minTime = Math.min(...timetable.trains.flatMap(t => t.operations).map(op => op.time));
-    const maxTime = Math.max(...timetable.trains.flatMap(t => t.operations).map(op => op.time));
-    const planeMaterial = new THREE.MeshBasicMaterial({color: "white", side: THREE.DoubleSide});
-    planeMaterial.transparent = true;
-    planeMaterial.opacity = 0.4;
-    for(const res of infrastructure.resources) {
-      let x1 = infrastructure.nodes[res.node_lo].location.x;
-      let y1 = infrastructure.nodes[res.node_lo].location.y;
-      let z1 = minTime * time_scale;
-      let x2 = infrastructure.nodes[res.node_hi].location.x;
-      let y2 = infrastructure.nodes[res.node_hi].location.y;
-      let z2 = maxTime * time_scale;
-
-      
-      
-      let planeGeom = new THREE.BufferGeometry();
-      planeGeom.setAttribute( 'position', new THREE.BufferAttribute( new Float32Array([
-        x1,y1,z1,
-        x2,y2,z1,
-        x1,y1,z2,
-        x2,y2,z2,
-      ]), 3 ));
-
-      planeGeom.setIndex([0,1,2,1,3,2]);
-      var mesh2 = new THREE.Mesh(planeGeom, planeMaterial);
-
-      scene.add( mesh2 );
+    let planeGeom = new THREE.BufferGeometry();
+    planeGeom.setAttribute( 'position', new THREE.BufferAttribute( new Float32Array([
+      x1,y1,z1,
+      x2,y2,z1,
+      x1,y1,z2,
+      x2,y2,z2,
+    ]), 3 ));
+
+    planeGeom.setIndex([0,1,2,1,3,2]);
+    var mesh2 = new THREE.Mesh(planeGeom, planeMaterial);
+
+    scene.add( mesh2 );
+  }
+}
+
+// How many trains a resource can hold at once. Double tracks hold one train
+// per direction.
+function resource_capacity(res) {
+  return res.restype.type === "Station" ? res.restype.capacity : 1;
+}
+
+function occupation_key(res, op) {
+  return res.restype.type === "DoubleTrack" ? op.forward : true;
+}
+
+// Every time a resource gets more trains than its capacity, with the trains
+// involved.
+function find_conflicts(infrastructure, timetable, times) {
+  const visits = infrastructure.resources.map(() => []);
+  for(const [train_idx, train] of timetable.trains.entries()) {
+    for(const [op_idx, op] of train.operations.entries()) {
+      const [time_in, time_out] = times[train_idx][op_idx];
+      visits[op.resource].push({ train: train_idx, key: occupation_key(infrastructure.resources[op.resource], op), time_in, time_out });
+    }
+  }
+
+  const conflicts = [];
+  for(const [res_idx, res_visits] of visits.entries()) {
+    const capacity = resource_capacity(infrastructure.resources[res_idx]);
+    res_visits.sort((a, b) => a.time_in - b.time_in);
+    for(const [i, v] of res_visits.entries()) {
+      const occupants = res_visits.slice(0, i + 1).filter(o => o.key === v.key && o.time_out > v.time_in);
+      if (occupants.length > capacity) {
+        conflicts.push({ resource: res_idx, time: v.time_in, trains: occupants.map(o => o.train) });
+      }
     }
   }
+  conflicts.sort((a, b) => a.time - b.time);
+  return conflicts;
+}
+
+function add_animation(infrastructure, timetable, solution) {
+  const times = operation_times(timetable, solution);
+  const conflicts = find_conflicts(infrastructure, timetable, times);
+  const allTimes = times.flat().flat();
+  const minTime = Math.min(...allTimes);
+  const maxTime = Math.max(...allTimes);
+
+  const n_trains = timetable.trains.length;
+  const markerGeometry = new THREE.BufferGeometry();
+  markerGeometry.setAttribute('position', new THREE.Float32BufferAttribute(new Float32Array(3 * n_trains), 3));
+  markerGeometry.setAttribute('color', new THREE.Float32BufferAttribute(new Float32Array(3 * n_trains), 3));
+  const markers = new THREE.Points(markerGeometry, new THREE.PointsMaterial({ size: 8, sizeAttenuation: false, vertexColors: true }));
+  scene.add(markers);
+
+  const conflictGeometry = new THREE.BufferGeometry();
+  conflictGeometry.setAttribute('position', new THREE.Float32BufferAttribute(new Float32Array(6 * infrastructure.resources.length), 3));
+  const conflictLines = new THREE.LineSegments(conflictGeometry, material_conflict);
+  scene.add(conflictLines);
+
+  const slider = document.getElementById("time");
+  const play = document.getElementById("play");
+  const label = document.getElementById("time_label");
+  const list = document.getElementById("conflicts");
+  slider.min = minTime;
+  slider.max = maxTime;
+  slider.value = minTime;
+
+  list.replaceChildren(...conflicts.map(c => {
+    const item = document.createElement("li");
+    item.textContent = `${format_time(c.time)} resource ${c.resource}: trains ${c.trains.join(", ")}`;
+    item.onclick = () => { slider.value = c.time; update(); };
+    return item;
+  }));
+  document.getElementById("n_conflicts").textContent = `${conflicts.length} conflicts` + (solution ? " (solution)" : " (planned)");
+
+  let playing = false;
+  play.onclick = () => { playing = !playing; play.textContent = playing ? "Pause" : "Play"; };
+  slider.oninput = update;
+
+  function update() {
+    const t = Number(slider.value);
+    label.textContent = format_time(t);
+
+    // Current position of every running train, and resource occupation.
+    const running = [];
+    const occupation = new Map();
+    for(const [train_idx, train] of timetable.trains.entries()) {
+      for(const [op_idx, op] of train.operations.entries()) {
+        const [time_in, time_out] = times[train_idx][op_idx];
+        if (time_in <= t && t < time_out) {
+          const res = infrastructure.resources[op.resource];
+          const from = infrastructure.nodes[op.forward ? res.node_lo : res.node_hi].location;
+          const to = infrastructure.nodes[op.forward ? res.node_hi : res.node_lo].location;
+          const f = Math.min(1.0, (t - time_in) / Math.max(op.min_duration, 1e-9));
+          running.push({ train: train_idx, x: from.x + f * (to.x - from.x), y: from.y + f * (to.y - from.y) });
+
+          const key = `${op.resource}/${occupation_key(res, op)}`;
+          if (!occupation.has(key)) {
+            occupation.set(key, { resource: op.resource, trains: [] });
+          }
+          occupation.get(key).trains.push(train_idx);
+          break;
+        }
+      }
+    }
+
+    const conflicting = new Set();
+    const conflictPositions = conflictGeometry.attributes.position;
+    let n_conflict_resources = 0;
+    for(const { resource, trains } of occupation.values()) {
+      const res = infrastructure.resources[resource];
+      if (trains.length > resource_capacity(res)) {
+        trains.forEach(tr => conflicting.add(tr));
+        const lo = infrastructure.nodes[res.node_lo].location;
+        const hi = infrastructure.nodes[res.node_hi].location;
+        conflictPositions.setXYZ(2 * n_conflict_resources, lo.x, lo.y, t * time_scale);
+        conflictPositions.setXYZ(2 * n_conflict_resources + 1, hi.x, hi.y, t * time_scale);
+        n_conflict_resources += 1;
+      }
+    }
+    conflictPositions.needsUpdate = true;
+    conflictGeometry.setDrawRange(0, 2 * n_conflict_resources);
+
+    const positions = markerGeometry.attributes.position;
+    const colors = markerGeometry.attributes.color;
+    for(const [i, r] of running.entries()) {
+      positions.setXYZ(i, r.x, r.y, t * time_scale);
+      if (conflicting.has(r.train)) {
+        colors.setXYZ(i, 1.0, 0.0, 1.0);
+      } else {
+        colors.setXYZ(i, 0.0, 0.0, 0.0);
+      }
+    }
+    positions.needsUpdate = true;
+    colors.needsUpdate = true;
+    markerGeometry.setDrawRange(0, running.length);
+  }
+  update();
+
+  // Play at 2 minutes of schedule time per second.
+  let last_frame = performance.now();
+  animation_hooks.push(now => {
+    const dt = (now - last_frame) / 1000.0;
+    last_frame = now;
+    if (playing) {
+      slider.value = Math.min(maxTime, Number(slider.value) + 120.0 * dt);
+      update();
+    }
+  });
+}
+
+function format_time(t) {
+  const h = Math.floor(t / 3600);
+  const m = Math.floor((t - 3600 * h) / 60);
+  return `${h}:${String(m).padStart(2, "0")}`;
 }
 
 
@@ -139,8 +329,12 @@ camera.position.x = 5;
 const controls = new OrbitControls( camera, renderer.domElement );
 controls.update();
 
-function animate() {
+const animation_hooks = [];
+function animate(now) {
+    for (const hook of animation_hooks) {
+        hook(now);
+    }
 	renderer.render( scene, camera );
     controls.update();
 }
-renderer.setAnimationLoop( animate );
\ No newline at end of file
+renderer.setAnimationLoop( animate );
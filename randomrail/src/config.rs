@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+use crate::scenario::DelayDistribution;
+
+/// All parameters of the instance generator. Together with the seed, this
+/// determines the generated infrastructure and timetable exactly.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// Random seed. When missing, a random seed is chosen and recorded.
+    pub seed: Option<u64>,
+
+    // Infrastructure
+    pub main_station_capacity: usize,
+    pub main_line_min_stations: usize,
+    pub main_line_max_stations: usize,
+    pub main_line_avg_dist: f64,
+    pub branch_min_stations: usize,
+    pub branch_max_stations: usize,
+    pub branch_avg_dist: f64,
+    pub west_n_lines: usize,
+    pub east_n_lines: usize,
+
+    // Timetable
+    pub speed: f64,
+    pub span: f64,
+    pub slack: f64,
+    pub main_period: f64,
+
+    // Disjunctive conversion
+    pub max_delay: i32,
+    /// Seconds between trains following each other on a double track.
+    pub headway: i32,
+
+    // Delay scenario
+    pub delays: DelayConfig,
+}
+
+/// Primary delays for rescheduling scenarios.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DelayConfig {
+    /// Random seed of the scenario, separate from the instance's seed so
+    /// that the same timetable can get different delays. When missing, the
+    /// instance's seed is used.
+    pub seed: Option<u64>,
+    /// Fraction of trains that get a primary delay.
+    pub train_fraction: f64,
+    /// Distribution of the delay in seconds.
+    pub distribution: DelayDistribution,
+}
+
+impl Default for DelayConfig {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            train_fraction: 0.2,
+            distribution: DelayDistribution::Exponential { mean: 5.0 * 60.0 },
+        }
+    }
+}
+
+impl Config {
+    /// Check the parameters that the generator would otherwise panic on or
+    /// turn into a meaningless instance.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.main_station_capacity == 0 {
+            return Err("main_station_capacity must be at least 1".to_string());
+        }
+        if self.main_line_min_stations > self.main_line_max_stations
+            || self.branch_min_stations > self.branch_max_stations
+        {
+            return Err("the minimum number of stations of a line exceeds its maximum".to_string());
+        }
+        if self.west_n_lines == 0 || self.east_n_lines == 0 {
+            return Err("there must be at least one west and one east line".to_string());
+        }
+        // All but one west line start at distinct stations of the main line
+        // other than its ends.
+        if self.west_n_lines > self.main_line_min_stations {
+            return Err(format!(
+                "west_n_lines ({}) must not exceed main_line_min_stations ({})",
+                self.west_n_lines, self.main_line_min_stations
+            ));
+        }
+        if self.headway <= 0 {
+            return Err(format!("headway must be positive, got {}", self.headway));
+        }
+        self.delays.distribution.validate()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            main_station_capacity: 20,
+            main_line_min_stations: 7,
+            main_line_max_stations: 15,
+            main_line_avg_dist: 4.0,
+            branch_min_stations: 7,
+            branch_max_stations: 20,
+            branch_avg_dist: 10.0,
+            west_n_lines: 3,
+            east_n_lines: 4,
+            speed: 60.0 /* km/h */ / 3600.0, /* km/sec */
+            span: 6. * 3600.,                // 6 hours
+            slack: 1.04,                     // 4% running time slack
+            main_period: 10.0 * 60.0,        // bottleneck frequency
+            max_delay: 15 * 60,              // 15 minutes
+            headway: 2 * 60,                 // 2 minutes
+            delays: Default::default(),
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use serde::Deserialize;
+
+use crate::{
+    disjunctive::train_nodes,
+    infrastructure::{Infrastructure, ResourceType},
+    timetable::Timetable,
+};
+
+/// The solver's output: the time of every node in the disjunctive graph.
+#[derive(Deserialize)]
+pub struct Solution {
+    pub positions: Vec<i32>,
+}
+
+const WIDTH: f64 = 2000.0;
+const HEIGHT: f64 = 1000.0;
+const MARGIN: f64 = 60.0;
+
+/// One train's pass over a resource on the corridor, in corridor distance.
+struct Pass {
+    train: usize,
+    resource: usize,
+    from: f64,
+    to: f64,
+    planned: (f64, f64),
+    actual: (f64, f64),
+}
+
+/// Render a time–space diagram along the route of train `corridor_train`.
+///
+/// Planned paths are drawn dashed, rescheduled paths solid. At single-track
+/// sections, the order chosen by the solver is drawn as a line from one
+/// train's exit to the next train's entry, highlighted when it differs from
+/// the planned order.
+pub fn time_space_svg(
+    infrastructure: &Infrastructure,
+    timetable: &Timetable,
+    solution: &Solution,
+    corridor_train: usize,
+) -> String {
+    let train_nodes = train_nodes(timetable);
+
+    // Distance interval of each corridor resource, in the corridor train's
+    // direction of travel.
+    let mut corridor: BTreeMap<usize, (f64, f64, bool)> = Default::default();
+    let mut corridor_length = 0.0;
+    for op in timetable.trains[corridor_train].operations.iter() {
+        let length = infrastructure.resources[op.resource].length;
+        corridor.insert(
+            op.resource,
+            (corridor_length, corridor_length + length, op.forward),
+        );
+        corridor_length += length;
+    }
+
+    let mut passes = Vec::new();
+    for (train_idx, train) in timetable.trains.iter().enumerate() {
+        for (op_idx, op) in train.operations.iter().enumerate() {
+            let Some((lo, hi, forward)) = corridor.get(&op.resource).copied() else {
+                continue;
+            };
+            let (from, to) = if op.forward == forward {
+                (lo, hi)
+            } else {
+                (hi, lo)
+            };
+            let planned_out = train
+                .operations
+                .get(op_idx + 1)
+                .map(|next| next.time)
+                .unwrap_or(op.time + op.min_duration);
+            let nodes = &train_nodes[train_idx];
+            passes.push(Pass {
+                train: train_idx,
+                resource: op.resource,
+                from,
+                to,
+                planned: (op.time, planned_out),
+                actual: (
+                    solution.positions[nodes[op_idx] as usize] as f64,
+                    solution.positions[nodes[op_idx + 1] as usize] as f64,
+                ),
+            });
+        }
+    }
+
+    let t_min = passes
+        .iter()
+        .map(|p| p.planned.0.min(p.actual.0))
+        .fold(f64::INFINITY, f64::min);
+    let t_max = passes
+        .iter()
+        .map(|p| p.planned.1.max(p.actual.1))
+        .fold(f64::NEG_INFINITY, f64::max);
+    let x = |t: f64| MARGIN + (t - t_min) / (t_max - t_min).max(1.0) * (WIDTH - 2.0 * MARGIN);
+    let y = |d: f64| MARGIN + d / corridor_length.max(1e-9) * (HEIGHT - 2.0 * MARGIN);
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" font-family="sans-serif" font-size="10">"#
+    )
+    .unwrap();
+    writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#).unwrap();
+
+    // Stations as shaded bands, single tracks labelled.
+    for (resource, (lo, hi, _)) in corridor.iter() {
+        match infrastructure.resources[*resource].restype {
+            ResourceType::Station { capacity } => {
+                writeln!(
+                    svg,
+                    r##"<rect x="{}" y="{:.1}" width="{}" height="{:.1}" fill="#eeeeee"/><text x="2" y="{:.1}">{} ({})</text>"##,
+                    MARGIN,
+                    y(*lo),
+                    WIDTH - 2.0 * MARGIN,
+                    (y(*hi) - y(*lo)).max(1.0),
+                    y(*lo) + 4.0,
+                    resource,
+                    capacity,
+                )
+                .unwrap();
+            }
+            ResourceType::SingleTrack => {
+                writeln!(
+                    svg,
+                    r##"<text x="{}" y="{:.1}" fill="#888888">single</text>"##,
+                    WIDTH - MARGIN + 4.0,
+                    y((lo + hi) / 2.0),
+                )
+                .unwrap();
+            }
+            ResourceType::DoubleTrack => {}
+        }
+    }
+
+    // Hour grid.
+    let mut hour = (t_min / 3600.0).ceil();
+    while hour * 3600.0 <= t_max {
+        let hx = x(hour * 3600.0);
+        writeln!(
+            svg,
+            r##"<line x1="{hx:.1}" y1="{MARGIN}" x2="{hx:.1}" y2="{}" stroke="#cccccc"/><text x="{hx:.1}" y="{}">{}h</text>"##,
+            HEIGHT - MARGIN,
+            MARGIN - 8.0,
+            hour as i64,
+        )
+        .unwrap();
+        hour += 1.0;
+    }
+
+    // Train paths.
+    for train in 0..timetable.trains.len() {
+        let train_passes = passes.iter().filter(|p| p.train == train);
+        let mut planned = String::new();
+        let mut actual = String::new();
+        let mut up = true;
+        for p in train_passes {
+            up = p.to > p.from;
+            write!(
+                planned,
+                "{:.1},{:.1} {:.1},{:.1} ",
+                x(p.planned.0),
+                y(p.from),
+                x(p.planned.1),
+                y(p.to)
+            )
+            .unwrap();
+            write!(
+                actual,
+                "{:.1},{:.1} {:.1},{:.1} ",
+                x(p.actual.0),
+                y(p.from),
+                x(p.actual.1),
+                y(p.to)
+            )
+            .unwrap();
+        }
+        if actual.is_empty() {
+            continue;
+        }
+        let color = if up { "#1f5fbf" } else { "#bf1f1f" };
+        writeln!(
+            svg,
+            r##"<polyline points="{planned}" fill="none" stroke="#999999" stroke-dasharray="4 3"/>"##
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"<polyline points="{actual}" fill="none" stroke="{color}" stroke-width="1.5"><title>train {train}</title></polyline>"#
+        )
+        .unwrap();
+    }
+
+    // Disjunctive choices at single-track sections: consecutive trains in the
+    // solution's order, with reorderings relative to the plan highlighted.
+    for (resource, _) in corridor.iter() {
+        if !matches!(
+            infrastructure.resources[*resource].restype,
+            ResourceType::SingleTrack
+        ) {
+            continue;
+        }
+        let mut visits = passes
+            .iter()
+            .filter(|p| p.resource == *resource)
+            .collect::<Vec<_>>();
+        visits.sort_by(|a, b| a.actual.0.total_cmp(&b.actual.0));
+        for pair in visits.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let reordered = a.planned.0 > b.planned.0;
+            let (color, width) = if reordered {
+                ("#d000d0", 2.0)
+            } else {
+                ("#f0a000", 1.0)
+            };
+            writeln!(
+                svg,
+                r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{color}" stroke-width="{width}"><title>train {} before train {}{}</title></line>"#,
+                x(a.actual.1),
+                y(a.to),
+                x(b.actual.0),
+                y(b.from),
+                a.train,
+                b.train,
+                if reordered { " (reordered)" } else { "" },
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
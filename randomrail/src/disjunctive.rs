@@ -0,0 +1,399 @@
+use serde::Serialize;
+
+use crate::{
+    infrastructure::{Infrastructure, ResourceType},
+    timetable::Timetable,
+};
+
+#[derive(Serialize, Default, Clone, Copy, Debug)]
+pub struct Edge {
+    pub src: u32,
+    pub tgt: u32,
+    pub weight: i32,
+}
+
+#[derive(Serialize)]
+pub struct Node {
+    pub lb: i32,
+    pub ub: i32,
+    pub coeff: u32,
+    pub threshold: i32,
+}
+
+#[derive(Serialize)]
+pub struct DisjunctiveGraph {
+    pub nodes: Vec<Node>,
+    pub edge_sets: Vec<Vec<Edge>>,
+}
+
+/// One train's occupation of a resource, from entering it (`node_in`) until
+/// entering the next resource (`node_out`).
+#[derive(Clone, Copy, Debug)]
+struct Visit {
+    node_in: u32,
+    node_out: u32,
+    planned_in: i32,
+    planned_out: i32,
+    forward: bool,
+}
+
+/// The node indices of each train's operations, followed by the node of its
+/// final exit. This is the node numbering used by `convert`.
+pub fn train_nodes(timetable: &Timetable) -> Vec<Vec<u32>> {
+    let mut next_node = 0;
+    timetable
+        .trains
+        .iter()
+        .map(|train| {
+            let nodes = (next_node..(next_node + train.operations.len() as u32 + 1)).collect();
+            next_node += train.operations.len() as u32 + 1;
+            nodes
+        })
+        .collect()
+}
+
+/// Convert a timetable into a disjunctive graph where every node is a train
+/// entering a resource (plus one node for leaving the last resource).
+///
+/// No node may be delayed more than `max_delay` seconds from its planned
+/// time. This makes visits that are planned far enough apart unable to
+/// conflict, so they get no disjunction. It also means that the planned
+/// times must satisfy every disjunction, see `resolve_conflicts`.
+///
+/// Single tracks and station tracks are exclusive resources. On a double
+/// track, trains in the same direction keep `headway` seconds apart when
+/// entering and when leaving, see `add_headway_constraints`. A station with
+/// `k` tracks is modelled by its minimal forbidden sets: for every `k + 1`
+/// visits that could overlap, some pair of them must be ordered. Each such
+/// set becomes one disjunction with an alternative for every ordered pair.
+/// If a station has more than `MAX_FORBIDDEN_SETS` of them, its visits are
+/// instead assigned to tracks by planned time, and each track is exclusive,
+/// which is more restrictive but keeps the graph small.
+///
+/// Returns the graph and the node indices from `train_nodes`.
+pub fn convert(
+    infrastructure: &Infrastructure,
+    timetable: &Timetable,
+    max_delay: i32,
+    headway: i32,
+) -> (DisjunctiveGraph, Vec<Vec<u32>>) {
+    let mut graph = DisjunctiveGraph {
+        nodes: Default::default(),
+        edge_sets: Default::default(),
+    };
+    let train_nodes = train_nodes(timetable);
+    let mut resource_visits: Vec<Vec<Visit>> = infrastructure
+        .resources
+        .iter()
+        .map(|_| Default::default())
+        .collect();
+
+    for (train, nodes) in timetable.trains.iter().zip(train_nodes.iter()) {
+        for op in train.operations.iter() {
+            graph.nodes.push(Node {
+                lb: op.time.round() as i32,
+                ub: op.time.round() as i32 + max_delay,
+                coeff: 0,
+                threshold: 0,
+            });
+        }
+
+        // The final exit node carries the delay cost.
+        let last_op = train.operations.last().unwrap();
+        let planned_exit = (last_op.time + last_op.min_duration).round() as i32;
+        graph.nodes.push(Node {
+            lb: planned_exit,
+            ub: planned_exit + max_delay,
+            coeff: 1,
+            threshold: planned_exit,
+        });
+
+        for (op_idx, op) in train.operations.iter().enumerate() {
+            graph.edge_sets.push(vec![Edge {
+                src: nodes[op_idx],
+                tgt: nodes[op_idx + 1],
+                weight: op.min_duration.floor() as i32,
+            }]);
+
+            resource_visits[op.resource].push(Visit {
+                node_in: nodes[op_idx],
+                node_out: nodes[op_idx + 1],
+                planned_in: graph.nodes[nodes[op_idx] as usize].lb,
+                planned_out: graph.nodes[nodes[op_idx + 1] as usize].lb,
+                forward: op.forward,
+            });
+        }
+    }
+
+    for (resource, visits) in infrastructure.resources.iter().zip(resource_visits) {
+        match resource.restype {
+            ResourceType::SingleTrack => {
+                add_capacity_constraints(&mut graph, visits, 1, max_delay);
+            }
+            ResourceType::DoubleTrack => {
+                let (up, down) = visits.into_iter().partition(|v| v.forward);
+                add_headway_constraints(&mut graph, up, headway, max_delay);
+                add_headway_constraints(&mut graph, down, headway, max_delay);
+            }
+            ResourceType::Station { capacity } => {
+                add_capacity_constraints(&mut graph, visits, capacity, max_delay);
+            }
+        }
+    }
+
+    (graph, train_nodes)
+}
+
+/// Trains in the same direction on a double track keep `headway` seconds
+/// apart both when entering and when leaving it, so they cannot overtake on
+/// the track: if `a` enters first, `a.in + headway <= b.in` and
+/// `a.out + headway <= b.out`, and the other way around if `b` enters first.
+/// As every alternative is a single edge, this takes two disjunctions per
+/// pair, {a enters first, b leaves first} and {b enters first, a leaves
+/// first}. Choosing that `a` enters first in one rules out that `b` enters
+/// first in the other, which leaves `a` leaving first.
+fn add_headway_constraints(
+    graph: &mut DisjunctiveGraph,
+    visits: Vec<Visit>,
+    headway: i32,
+    max_delay: i32,
+) {
+    // Given the maximum delay, `x` always enters and leaves at least
+    // `headway` before `y` if it is planned far enough ahead.
+    let apart = |x: &Visit, y: &Visit| {
+        y.planned_in >= x.planned_in + max_delay + headway
+            && y.planned_out >= x.planned_out + max_delay + headway
+    };
+    let edge = |src: u32, tgt: u32| Edge {
+        src,
+        tgt,
+        weight: headway,
+    };
+    for (i, a) in visits.iter().enumerate() {
+        for b in visits[(i + 1)..].iter() {
+            if apart(a, b) || apart(b, a) {
+                continue;
+            }
+            graph.edge_sets.push(vec![
+                edge(a.node_in, b.node_in),
+                edge(b.node_out, a.node_out),
+            ]);
+            graph.edge_sets.push(vec![
+                edge(b.node_in, a.node_in),
+                edge(a.node_out, b.node_out),
+            ]);
+        }
+    }
+}
+
+/// The number of forbidden sets of a station above which its visits are
+/// assigned to tracks instead. The number of sets grows with the number of
+/// overlapping visits to the power of the capacity plus one. Exclusive
+/// resources only have pairs, so they are not limited.
+const MAX_FORBIDDEN_SETS: usize = 10_000;
+
+fn add_capacity_constraints(
+    graph: &mut DisjunctiveGraph,
+    mut visits: Vec<Visit>,
+    capacity: usize,
+    max_delay: i32,
+) {
+    assert!(capacity >= 1);
+    visits.sort_by_key(|v| v.planned_in);
+
+    let limit = if capacity == 1 {
+        usize::MAX
+    } else {
+        MAX_FORBIDDEN_SETS
+    };
+    let mut sets = Vec::new();
+    let mut set = Vec::new();
+    let complete = (0..visits.len()).all(|first| {
+        set.push(first);
+        let complete = forbidden_sets(&visits, capacity + 1, max_delay, limit, &mut set, &mut sets);
+        set.pop();
+        complete
+    });
+    if !complete {
+        eprintln!(
+            "more than {} forbidden sets for {} visits with capacity {}, assigning tracks instead",
+            MAX_FORBIDDEN_SETS,
+            visits.len(),
+            capacity
+        );
+        for track in assign_tracks(visits, capacity) {
+            add_capacity_constraints(graph, track, 1, max_delay);
+        }
+        return;
+    }
+
+    for set in sets {
+        let mut alternatives = Vec::new();
+        for a in set.iter() {
+            for b in set.iter() {
+                if a != b {
+                    alternatives.push(Edge {
+                        src: visits[*a].node_out,
+                        tgt: visits[*b].node_in,
+                        weight: 0,
+                    });
+                }
+            }
+        }
+        graph.edge_sets.push(alternatives);
+    }
+}
+
+/// Assign visits sorted by planned entry to `capacity` tracks, each visit
+/// to the track that is free first according to the plan.
+fn assign_tracks(visits: Vec<Visit>, capacity: usize) -> Vec<Vec<Visit>> {
+    let mut tracks = vec![Vec::new(); capacity];
+    let mut free_at = vec![i32::MIN; capacity];
+    for visit in visits {
+        let track = (0..capacity).min_by_key(|t| free_at[*t]).unwrap();
+        free_at[track] = visit.planned_out;
+        tracks[track].push(visit);
+    }
+    tracks
+}
+
+/// Extend `set` with later visits that can overlap all of its members, and
+/// collect every set of size `size` in `sets`. Returns false, leaving
+/// `sets` incomplete, once there are more than `limit`.
+///
+/// Two visits can only overlap if the later one is planned to enter before
+/// the earlier one can have left, given the maximum delay.
+fn forbidden_sets(
+    visits: &[Visit],
+    size: usize,
+    max_delay: i32,
+    limit: usize,
+    set: &mut Vec<usize>,
+    sets: &mut Vec<Vec<usize>>,
+) -> bool {
+    if set.len() == size {
+        sets.push(set.clone());
+        return sets.len() <= limit;
+    }
+
+    // Visits are sorted by planned entry, so we can stop at the first visit
+    // that enters after some member of the set has certainly left.
+    let latest_in = set
+        .iter()
+        .map(|i| visits[*i].planned_out + max_delay)
+        .min()
+        .unwrap();
+
+    let last = *set.last().unwrap();
+    for next in (last + 1)..visits.len() {
+        if visits[next].planned_in >= latest_in {
+            break;
+        }
+        set.push(next);
+        let complete = forbidden_sets(visits, size, max_delay, limit, set, sets);
+        set.pop();
+        if !complete {
+            return false;
+        }
+    }
+    true
+}
+
+/// The edge sets that none of the alternatives is satisfied for by `times`.
+pub fn violated_edge_sets(graph: &DisjunctiveGraph, times: &[i32]) -> Vec<usize> {
+    (0..graph.edge_sets.len())
+        .filter(|i| !satisfied(&graph.edge_sets[*i], times))
+        .collect()
+}
+
+fn satisfied(edges: &[Edge], times: &[i32]) -> bool {
+    edges
+        .iter()
+        .any(|e| times[e.src as usize] + e.weight <= times[e.tgt as usize])
+}
+
+/// Delay the planned times in `timetable` until they satisfy every
+/// disjunction of the converted graph, so that the plan itself is a solution
+/// and the `ub`s derived from it leave room for rescheduling. Delaying trains
+/// can bring visits within `max_delay` of each other that got no disjunction
+/// before, so this converts and dispatches again until nothing changes.
+pub fn resolve_conflicts(
+    infrastructure: &Infrastructure,
+    timetable: &mut Timetable,
+    max_delay: i32,
+    headway: i32,
+) {
+    loop {
+        let (graph, train_nodes) = convert(infrastructure, timetable, max_delay, headway);
+        let times = dispatch(&graph, &train_nodes);
+        if graph
+            .nodes
+            .iter()
+            .zip(times.iter())
+            .all(|(n, t)| n.lb == *t)
+        {
+            return;
+        }
+        for (train, nodes) in timetable.trains.iter_mut().zip(train_nodes.iter()) {
+            for (op, node) in train.operations.iter_mut().zip(nodes.iter()) {
+                op.time = times[*node as usize] as f64;
+            }
+        }
+    }
+}
+
+/// Node times from the `lb`s that satisfy every edge set. Trains are added
+/// in order of departure, and each conflict with the trains added before is
+/// resolved by letting the new train wait as little as possible. As the
+/// earlier trains are never moved, this cannot deadlock.
+fn dispatch(graph: &DisjunctiveGraph, train_nodes: &[Vec<u32>]) -> Vec<i32> {
+    let mut times = graph.nodes.iter().map(|n| n.lb).collect::<Vec<_>>();
+    let mut train_of = vec![0; graph.nodes.len()];
+    for (train, nodes) in train_nodes.iter().enumerate() {
+        for node in nodes.iter() {
+            train_of[*node as usize] = train;
+        }
+    }
+    let mut running_times = vec![Vec::new(); graph.nodes.len()];
+    for edges in graph.edge_sets.iter().filter(|es| es.len() == 1) {
+        running_times[edges[0].src as usize].push(edges[0]);
+    }
+
+    let mut order = (0..train_nodes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|t| times[train_nodes[*t][0] as usize]);
+    let mut rank = vec![0; train_nodes.len()];
+    for (i, train) in order.iter().enumerate() {
+        rank[*train] = i;
+    }
+
+    // Each disjunction is resolved when the last of its trains is added.
+    let mut disjunctions = vec![Vec::new(); train_nodes.len()];
+    for edges in graph.edge_sets.iter().filter(|es| es.len() >= 2) {
+        let last = edges
+            .iter()
+            .flat_map(|e| [e.src, e.tgt])
+            .map(|n| train_of[n as usize])
+            .max_by_key(|t| rank[*t])
+            .unwrap();
+        disjunctions[last].push(edges);
+    }
+
+    for train in order {
+        while let Some(edges) = disjunctions[train].iter().find(|es| !satisfied(es, &times)) {
+            let edge = edges
+                .iter()
+                .filter(|e| train_of[e.tgt as usize] == train)
+                .min_by_key(|e| times[e.src as usize] + e.weight - times[e.tgt as usize])
+                .unwrap();
+            let mut stack = vec![*edge];
+            while let Some(e) = stack.pop() {
+                let time = times[e.src as usize] + e.weight;
+                if time > times[e.tgt as usize] {
+                    times[e.tgt as usize] = time;
+                    stack.extend(running_times[e.tgt as usize].iter().copied());
+                }
+            }
+        }
+    }
+    times
+}
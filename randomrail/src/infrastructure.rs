@@ -1,24 +1,23 @@
-use std::{f64::consts::TAU, hash::DefaultHasher};
+use std::f64::consts::TAU;
 
-use rand::{
-    seq::{self, SliceRandom},
-    Rng,
-};
+use rand::{seq, Rng};
 use rand_distr::{Distribution, Normal};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Clone, Copy)]
+use crate::config::Config;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
 pub struct Vec2 {
     pub x: f64,
     pub y: f64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Node {
     pub location: Vec2,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Resource {
     pub length: f64,
     pub node_lo: usize,
@@ -26,7 +25,7 @@ pub struct Resource {
     pub restype: ResourceType,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ResourceType {
     SingleTrack,
@@ -34,14 +33,16 @@ pub enum ResourceType {
     Station { capacity: usize },
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Infrastructure {
     pub nodes: Vec<Node>,
     pub resources: Vec<Resource>,
 }
 
-pub fn generate_infrastructure() -> (Infrastructure, Vec<Vec<Vec<usize>>>, usize) {
-    let mut rng = rand::thread_rng();
+pub fn generate_infrastructure(
+    config: &Config,
+    rng: &mut impl Rng,
+) -> (Infrastructure, Vec<Vec<Vec<usize>>>, usize) {
     let mut infrastructure = Infrastructure {
         nodes: Default::default(),
         resources: Default::default(),
@@ -59,16 +60,19 @@ pub fn generate_infrastructure() -> (Infrastructure, Vec<Vec<Vec<usize>>>, usize
         length: 0.5,
         node_lo: main_station_west,
         node_hi: main_station_east,
-        restype: ResourceType::Station { capacity: 20 },
+        restype: ResourceType::Station {
+            capacity: config.main_station_capacity,
+        },
     });
 
     // main double track line
     let mut main_line = add_simple_line(
+        rng,
         &mut infrastructure,
         main_station_west,
-        7,
-        15,
-        4.0,
+        config.main_line_min_stations,
+        config.main_line_max_stations,
+        config.main_line_avg_dist,
         true,
         false,
         Vec2 { x: -1.0, y: 0.0 },
@@ -78,18 +82,19 @@ pub fn generate_infrastructure() -> (Infrastructure, Vec<Vec<Vec<usize>>>, usize
     println!("main line {:?}", main_line);
     println!("main line length {}", infrastructure.resources.iter().map(|r| r.length).sum::<f64>());
 
-    let west_n_lines = 3;
-    let east_n_lines = 4;
+    let west_n_lines = config.west_n_lines;
+    let east_n_lines = config.east_n_lines;
 
     // East lines are connected to the main_station_east
-    let mut east_lines = (0..east_n_lines)
+    let east_lines = (0..east_n_lines)
         .map(|i| {
             add_simple_line(
+                rng,
                 &mut infrastructure,
                 main_station_east,
-                7,
-                20,
-                10.0,
+                config.branch_min_stations,
+                config.branch_max_stations,
+                config.branch_avg_dist,
                 false,
                 true,
                 line_direction(0.0, i, east_n_lines),
@@ -125,7 +130,7 @@ pub fn generate_infrastructure() -> (Infrastructure, Vec<Vec<Vec<usize>>>, usize
         .collect::<Vec<_>>();
 
     let mut west_origin_station_idxs =
-        rand::seq::index::sample(&mut rng, connection_points.len(), west_n_lines - 1).into_vec();
+        rand::seq::index::sample(rng, connection_points.len(), west_n_lines - 1).into_vec();
     west_origin_station_idxs.sort();
 
     let west_origin_stations = west_origin_station_idxs
@@ -148,11 +153,12 @@ pub fn generate_infrastructure() -> (Infrastructure, Vec<Vec<Vec<usize>>>, usize
             };
 
             add_simple_line(
+                rng,
                 &mut infrastructure,
                 node_idx,
-                7,
-                20,
-                10.0,
+                config.branch_min_stations,
+                config.branch_max_stations,
+                config.branch_avg_dist,
                 false,
                 false,
                 direction,
@@ -166,7 +172,7 @@ pub fn generate_infrastructure() -> (Infrastructure, Vec<Vec<Vec<usize>>>, usize
 
     let n_west_lines = west_lines.len();
     let n_east_lines = east_lines.len();
-    let groups = services_westeast_grouped(&mut rng, n_west_lines, n_east_lines);
+    let groups = services_westeast_grouped(rng, n_west_lines, n_east_lines);
 
     println!("east west {:?}", groups);
 
@@ -209,7 +215,7 @@ pub fn generate_infrastructure() -> (Infrastructure, Vec<Vec<Vec<usize>>>, usize
 }
 
 fn services_westeast_grouped(
-    rng: &mut rand::prelude::ThreadRng,
+    rng: &mut impl Rng,
     n_west_lines: usize,
     n_east_lines: usize,
 ) -> Vec<Vec<(usize, usize)>> {
@@ -220,7 +226,8 @@ fn services_westeast_grouped(
             Box::new(|_, b| b)
         };
 
-    let mut groups: std::collections::HashMap<usize, Vec<(usize, usize)>> = Default::default();
+    // Ordered map, so that the group order only depends on the random seed.
+    let mut groups: std::collections::BTreeMap<usize, Vec<(usize, usize)>> = Default::default();
 
     let west_shuffle = seq::index::sample(rng, n_west_lines, n_west_lines).into_vec();
     let east_shuffle = seq::index::sample(rng, n_east_lines, n_east_lines).into_vec();
@@ -239,6 +246,7 @@ fn services_westeast_grouped(
 
 #[allow(clippy::too_many_arguments)]
 pub fn add_simple_line(
+    rng: &mut impl Rng,
     infrastructure: &mut Infrastructure,
     mut track_start_node: usize,
     min_stations: usize,
@@ -249,7 +257,6 @@ pub fn add_simple_line(
     direction: Vec2,
 ) -> Vec<usize> {
     let mut line = vec![track_start_node];
-    let mut rng = rand::thread_rng();
 
     let station_tracks = if double_track {
         vec![4]
@@ -266,7 +273,7 @@ pub fn add_simple_line(
     println!("Line: generating {} stations", num_stations);
     let mut total_length = 0.0;
     for _ in 0..num_stations {
-        let track_length = dist.sample(&mut rng).max(min_dist);
+        let track_length = dist.sample(rng).max(min_dist);
         total_length += track_length;
         let start_loc = infrastructure.nodes[track_start_node].location;
         let track_end_loc = Vec2 {
@@ -352,9 +359,8 @@ fn line_direction(const_angle: f64, i: usize, n_lines: usize) -> Vec2 {
     let angle =
         (const_angle + -range + 2. * range * (i as f64 / (n_lines - 1).max(1) as f64)) * TAU;
     println!("angle1 {}", angle);
-    let direction = Vec2 {
+    Vec2 {
         x: angle.cos(),
         y: -angle.sin(),
-    };
-    direction
+    }
 }
@@ -1,12 +1,143 @@
+mod config;
+mod diagram;
+mod disjunctive;
 mod infrastructure;
 pub mod oldmain;
+mod scenario;
+mod serve;
 pub mod timetable;
+use config::Config;
 use infrastructure::generate_infrastructure;
+use rand::{rngs::StdRng, SeedableRng};
 use timetable::{generate_timetable, Timetable};
 
+fn usage() -> ! {
+    eprintln!("usage: randomrail [--config <file.json>] [--seed <u64>] [--scenario-seed <u64>]");
+    eprintln!(
+        "       randomrail diagram <i.json> <tt.json> <solution.json> <out.svg> [--train <n>]"
+    );
+    eprintln!("       randomrail serve [--port <port>]");
+    std::process::exit(1);
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(filename: &str) -> T {
+    serde_json::from_str(&std::fs::read_to_string(filename).unwrap()).unwrap()
+}
+
+fn diagram(args: &[String]) {
+    let (files, corridor_train) = match args {
+        [files @ .., flag, n] if flag == "--train" => {
+            (files, n.parse::<usize>().unwrap_or_else(|_| usage()))
+        }
+        files => (files, 0),
+    };
+    let [infrastructure, timetable, solution, output] = files else {
+        usage();
+    };
+    let timetable: Timetable = read_json(timetable);
+    if corridor_train >= timetable.trains.len() {
+        eprintln!(
+            "train {} does not exist, the timetable has {} trains",
+            corridor_train,
+            timetable.trains.len()
+        );
+        usage();
+    }
+    let svg = diagram::time_space_svg(
+        &read_json(infrastructure),
+        &timetable,
+        &read_json(solution),
+        corridor_train,
+    );
+    std::fs::write(output, svg).unwrap();
+}
+
 fn main() {
-    let (infrastructure, services, bottleneck) = generate_infrastructure();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(|a| a.as_str()) {
+        Some("diagram") => {
+            diagram(&args[1..]);
+            return;
+        }
+        Some("serve") => {
+            let port = match &args[1..] {
+                [] => 8000,
+                [flag, port] if flag == "--port" => port.parse().unwrap_or_else(|_| usage()),
+                _ => usage(),
+            };
+            serve::serve(port);
+            return;
+        }
+        _ => {}
+    }
+
+    let mut config_file = None;
+    let mut seed = None;
+    let mut scenario_seed = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_file = Some(args.next().unwrap_or_else(|| usage())),
+            "--seed" => {
+                seed = Some(
+                    args.next()
+                        .and_then(|s| s.parse::<u64>().ok())
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            "--scenario-seed" => {
+                scenario_seed = Some(
+                    args.next()
+                        .and_then(|s| s.parse::<u64>().ok())
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            _ => usage(),
+        }
+    }
+
+    let mut config: Config = config_file.map(|f| read_json(&f)).unwrap_or_default();
+    if let Err(e) = config.validate() {
+        eprintln!("invalid config: {}", e);
+        std::process::exit(1);
+    }
+
+    // A seed on the command line overrides the config file. Without either,
+    // pick a random seed; it is written to cfg1.json so the run can be repeated.
+    // The scenario seed defaults to the instance seed.
+    let seed = seed.or(config.seed).unwrap_or_else(rand::random);
+    config.seed = Some(seed);
+    println!("seed {}", seed);
+    let scenario_seed = scenario_seed.or(config.delays.seed).unwrap_or(seed);
+    config.delays.seed = Some(scenario_seed);
+    println!("scenario seed {}", scenario_seed);
+    std::fs::write("cfg1.json", serde_json::to_string_pretty(&config).unwrap()).unwrap();
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (infrastructure, services, bottleneck) = generate_infrastructure(&config, &mut rng);
     std::fs::write("i1.json", serde_json::to_string(&infrastructure).unwrap()).unwrap();
-    let timetable: Timetable = generate_timetable(&infrastructure, &services, bottleneck);
+    let mut timetable: Timetable =
+        generate_timetable(&config, &mut rng, &infrastructure, &services, bottleneck);
+    disjunctive::resolve_conflicts(
+        &infrastructure,
+        &mut timetable,
+        config.max_delay,
+        config.headway,
+    );
     std::fs::write("tt1.json", serde_json::to_string(&timetable).unwrap()).unwrap();
+    let (mut problem, train_nodes) =
+        disjunctive::convert(&infrastructure, &timetable, config.max_delay, config.headway);
+
+    // The plan must be a solution of the undelayed instance.
+    let planned = problem.nodes.iter().map(|n| n.lb).collect::<Vec<_>>();
+    let violated = disjunctive::violated_edge_sets(&problem, &planned);
+    assert!(violated.is_empty(), "the plan violates edge sets {:?}", violated);
+    std::fs::write("d1.json", serde_json::to_string(&problem).unwrap()).unwrap();
+
+    // Rescheduling scenario: the same instance with primary delays applied.
+    let mut scenario_rng = StdRng::seed_from_u64(scenario_seed);
+    let mut delays = scenario::generate_delays(&config.delays, &mut scenario_rng, &timetable);
+    scenario::apply_delays(&mut problem, &train_nodes, &mut delays);
+    std::fs::write("s1.json", serde_json::to_string(&delays).unwrap()).unwrap();
+    std::fs::write("d1_delayed.json", serde_json::to_string(&problem).unwrap()).unwrap();
 }
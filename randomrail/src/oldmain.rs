@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 use std::{collections::HashMap, f64::consts::TAU};
 
 use rand::Rng;
@@ -178,7 +180,7 @@ fn round_robin(lines :&[LineSpec], span :f64) {
     let period = 1.0 / total_freq;
     let mut t : f64 = 0.;
 
-    let mut queue = (0..lines.len()).collect::<Vec<_>>();
+    let _queue = (0..lines.len()).collect::<Vec<_>>();
     while t < span {
         // Sort the queue by (1) virtual time of next train
         //                   (2) absolute distance 
@@ -238,7 +240,7 @@ pub fn main_old() {
         println!("line freq {freq}");
 
         let line_trains = generate_trains(&infrastructure, line, SPEED, SPAN, SLACK, freq);
-        timetable.trains.extend(line_trains.into_iter());
+        timetable.trains.extend(line_trains);
     }
 
     std::fs::write("tt1.json", serde_json::to_string(&timetable).unwrap()).unwrap();
@@ -0,0 +1,102 @@
+use rand::Rng;
+use rand_distr::{Distribution, Exp, Normal};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::DelayConfig, disjunctive::DisjunctiveGraph, timetable::Timetable};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(tag = "type")]
+pub enum DelayDistribution {
+    Exponential { mean: f64 },
+    Normal { mean: f64, std_dev: f64 },
+    Uniform { min: f64, max: f64 },
+}
+
+impl DelayDistribution {
+    /// Check the parameters, so that sampling does not panic.
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            DelayDistribution::Exponential { mean } if !(mean > 0.0 && mean.is_finite()) => {
+                Err(format!("exponential delay mean must be positive, got {}", mean))
+            }
+            DelayDistribution::Normal { mean, std_dev }
+                if !(mean.is_finite() && std_dev >= 0.0 && std_dev.is_finite()) =>
+            {
+                Err(format!(
+                    "normal delay needs a finite mean and a non-negative std_dev, got {} and {}",
+                    mean, std_dev
+                ))
+            }
+            DelayDistribution::Uniform { min, max }
+                if !(min.is_finite() && max.is_finite() && min <= max) =>
+            {
+                Err(format!("uniform delay needs min <= max, got {} and {}", min, max))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Sample a delay in seconds. Negative samples are cut off at zero.
+    pub fn sample(&self, rng: &mut impl Rng) -> f64 {
+        let delay = match *self {
+            DelayDistribution::Exponential { mean } => Exp::new(1.0 / mean).unwrap().sample(rng),
+            DelayDistribution::Normal { mean, std_dev } => {
+                Normal::new(mean, std_dev).unwrap().sample(rng)
+            }
+            DelayDistribution::Uniform { min, max } => rng.gen_range(min..=max),
+        };
+        delay.max(0.0)
+    }
+}
+
+/// A train being delayed when starting one of its operations.
+#[derive(Serialize, Debug)]
+pub struct PrimaryDelay {
+    pub train: usize,
+    pub operation: usize,
+    pub delay: f64,
+}
+
+/// Choose `train_fraction` of the trains and give each a primary delay at a
+/// random operation.
+pub fn generate_delays(
+    config: &DelayConfig,
+    rng: &mut impl Rng,
+    timetable: &Timetable,
+) -> Vec<PrimaryDelay> {
+    let n_trains = timetable.trains.len();
+    let n_delayed = ((config.train_fraction * n_trains as f64).round() as usize).min(n_trains);
+    let mut trains = rand::seq::index::sample(rng, n_trains, n_delayed).into_vec();
+    trains.sort();
+
+    trains
+        .into_iter()
+        .map(|train| {
+            let operation = rng.gen_range(0..timetable.trains[train].operations.len());
+            let delay = config.distribution.sample(rng);
+            PrimaryDelay {
+                train,
+                operation,
+                delay,
+            }
+        })
+        .collect()
+}
+
+/// Raise the `lb` of the delayed nodes. Delays are capped so that no node
+/// gets an `lb` above its `ub`, which would make the instance infeasible, and
+/// a capped delay is changed to the delay that was applied.
+pub fn apply_delays(
+    problem: &mut DisjunctiveGraph,
+    train_nodes: &[Vec<u32>],
+    delays: &mut [PrimaryDelay],
+) {
+    for d in delays.iter_mut() {
+        let node = &mut problem.nodes[train_nodes[d.train][d.operation] as usize];
+        let lb = node.lb + d.delay.round() as i32;
+        if lb > node.ub {
+            d.delay = (node.ub - node.lb) as f64;
+        }
+        node.lb = lb.min(node.ub);
+    }
+}
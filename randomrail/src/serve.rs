@@ -0,0 +1,66 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::Path,
+};
+
+const INDEX_HTML: &str = include_str!("../index.html");
+const MAIN_JS: &str = include_str!("../main.js");
+
+/// Serve the viewer, and the JSON files in the current directory, on
+/// `http://localhost:<port>/`.
+pub fn serve(port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+    println!("serving on http://localhost:{}/", port);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle(stream) {
+                    println!("request failed: {}", e);
+                }
+            }
+            Err(e) => println!("connection failed: {}", e),
+        }
+    }
+}
+
+fn handle(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    // "GET /path?query HTTP/1.1"
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or("/")
+        .split('?')
+        .next()
+        .unwrap();
+    println!("GET {}", path);
+
+    let (status, content_type, body) = match path {
+        "/" | "/index.html" => ("200 OK", "text/html", INDEX_HTML.as_bytes().to_vec()),
+        "/main.js" => ("200 OK", "text/javascript", MAIN_JS.as_bytes().to_vec()),
+        _ => {
+            // Only plain JSON files from the current directory.
+            let filename = path.trim_start_matches('/');
+            let is_plain_json = Path::new(filename).extension().is_some_and(|e| e == "json")
+                && !filename.contains('/')
+                && !filename.contains('\\');
+            match is_plain_json.then(|| std::fs::read(filename)) {
+                Some(Ok(body)) => ("200 OK", "application/json", body),
+                _ => ("404 Not Found", "text/plain", b"not found".to_vec()),
+            }
+        }
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    stream.flush()
+}
@@ -1,42 +1,38 @@
 use std::collections::HashMap;
 
 use ordered_float::OrderedFloat;
-use rand::{thread_rng, Rng};
-use serde::Serialize;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-use crate::infrastructure::Infrastructure;
+use crate::{config::Config, infrastructure::Infrastructure};
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Timetable {
-    trains: Vec<Train>,
+    pub trains: Vec<Train>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Train {
-    operations: Vec<Operation>,
+    pub operations: Vec<Operation>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Operation {
-    resource: usize,
-    forward: bool,
-    min_duration: f64,
-    time: f64,
+    pub resource: usize,
+    pub forward: bool,
+    pub min_duration: f64,
+    pub time: f64,
 }
 
-const SPEED :f64 = 60.0 /* km/h */ / 3600.0 /* km/sec */;
-const SPAN: f64 = 6. * 3600.; // 6 hours
-const SLACK: f64 = 1.04; // 4% running time slack
-const MAIN_PERIOD: f64 = 10.0*60.0; // bottleneck frequency
-
 pub fn generate_timetable(
+    config: &Config,
+    rng: &mut impl Rng,
     infrastructure: &Infrastructure,
-    services: &Vec<Vec<Vec<usize>>>,
+    services: &[Vec<Vec<usize>>],
     bottleneck: usize,
 ) -> Timetable {
     let mut trains = Vec::new();
-    let mut rng = thread_rng();
-    let (group_freq, ingroup_freq) = generate_grouped_frequencies(services, &mut rng);
+    let (group_freq, ingroup_freq) = generate_grouped_frequencies(services, rng);
 
     for g in services.iter() {
         println!("group:");
@@ -48,12 +44,12 @@ pub fn generate_timetable(
     println!("freq {:?}", ingroup_freq);
 
     let mut t = 0.;
-    let mut group_counter = services.iter().map(|x| 0).collect::<Vec<_>>();
+    let mut group_counter = services.iter().map(|_| 0).collect::<Vec<_>>();
     let mut ingroup_counter = services
         .iter()
         .map(|x| x.iter().map(|_| 0).collect::<Vec<_>>())
         .collect::<Vec<_>>();
-    while t < SPAN {
+    while t < config.span {
         // Choose a service group.
         let g = (0..services.len())
             .min_by_key(|i| {
@@ -79,8 +75,8 @@ pub fn generate_timetable(
             trains.push(generate_train(
                 infrastructure,
                 &services[g][s],
-                SPEED,
-                SLACK,
+                config.speed,
+                config.slack,
                 up,
                 bottleneck,
                 t,
@@ -91,15 +87,15 @@ pub fn generate_timetable(
         group_counter[g] += 1;
         ingroup_counter[g][s] += 1;
 
-        t += MAIN_PERIOD;
+        t += config.main_period;
     }
 
     Timetable { trains }
 }
 
 fn generate_grouped_frequencies(
-    services: &Vec<Vec<Vec<usize>>>,
-    rng: &mut rand::prelude::ThreadRng,
+    services: &[Vec<Vec<usize>>],
+    rng: &mut impl Rng,
 ) -> (Vec<i32>, Vec<Vec<i32>>) {
     let group_freq = services
         .iter()
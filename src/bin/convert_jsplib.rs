@@ -25,6 +25,7 @@ pub struct DisjunctiveGraph {
 }
 
 #[derive(serde::Deserialize, Debug)]
+#[allow(dead_code)]
 struct Instance {
     name: String,
     jobs: u32,
@@ -35,13 +36,13 @@ struct Instance {
 }
 
 #[derive(serde::Deserialize, Debug)]
+#[allow(dead_code)]
 struct Bounds {
     upper: u32,
     lower: u32,
 }
 
 pub fn mk_disjunctive(name :&str, jobs: Vec<Vec<(usize, u32)>>) {
-    let n_jobs = jobs.len();
     let n_machines = jobs[0].len();
     assert!(jobs.iter().all(|j| j.len() == n_machines));
     for job in &jobs {
@@ -118,7 +119,7 @@ pub fn mk_disjunctive(name :&str, jobs: Vec<Vec<(usize, u32)>>) {
 
     let filename = format!("instances/jsp_{}.json", name);
     println!("Writing {}", filename);
-    std::fs::write(filename, serde_json::to_string(&problem).unwrap());
+    std::fs::write(filename, serde_json::to_string(&problem).unwrap()).unwrap();
 
 }
 
@@ -127,7 +128,7 @@ pub fn main() {
         serde_json::from_str(&std::fs::read_to_string("JSPLIB/instances.json").unwrap()).unwrap();
 
     for instance in instances {
-        let contents = std::fs::read_to_string(&format!("JSPLIB/{}", instance.path)).unwrap();
+        let contents = std::fs::read_to_string(format!("JSPLIB/{}", instance.path)).unwrap();
         println!("contents {}", contents);
         let mut size: Option<(usize, usize)> = None;
         let mut jobs: Vec<Vec<(usize, u32)>> = Default::default();
@@ -136,14 +137,7 @@ pub fn main() {
                 continue;
             }
             let split = line.split_ascii_whitespace().collect::<Vec<_>>();
-            if size.is_none() {
-                assert!(split.len() == 2);
-                size = Some((
-                    split[0].parse::<usize>().unwrap(),
-                    split[1].parse::<usize>().unwrap(),
-                ));
-            } else {
-                let (n_jobs, n_machines) = size.unwrap();
+            if let Some((_n_jobs, n_machines)) = size {
                 let mut new_job = Vec::new();
                 assert!(split.len() == n_machines * 2);
                 for i in 0..n_machines {
@@ -154,6 +148,12 @@ pub fn main() {
                 }
 
                 jobs.push(new_job);
+            } else {
+                assert!(split.len() == 2);
+                size = Some((
+                    split[0].parse::<usize>().unwrap(),
+                    split[1].parse::<usize>().unwrap(),
+                ));
             }
         }
 
@@ -1,29 +1,170 @@
 use std::{
     cmp::Reverse,
     collections::BinaryHeap,
-    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
     time::{Duration, Instant},
 };
 
-use log::{debug, info, trace};
+use log::{debug, info, trace, warn};
+use serde::Serialize;
 use tinyvec::TinyVec;
 
 use crate::{
-    problem::{DisjunctiveGraph, Edge},
+    problem::{Assignment, DisjunctiveGraph, Edge},
+    heuristic::HeuristicSearch,
+    presolve::{presolve, PresolveStats},
+    trace::{NodeStatus, SearchTrace, TraceNode, TraceRecorder},
     world::{State, World},
 };
 
+/// How to explore the search tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Complete best-first branch-and-bound.
+    BestFirst,
+    /// Depth-first search allowing at most `max_discrepancies` choices of a
+    /// child other than the one with the lowest `lb` on any path, with the
+    /// limit increased from 0 until `max_discrepancies`.
+    LimitedDiscrepancy { max_discrepancies: u32 },
+    /// Keep only the `width` nodes with the lowest `lb` on each level.
+    Beam { width: usize },
+}
+
+#[derive(Clone)]
 pub struct SolverSettings {
     pub use_strong_branching: bool,
     pub use_wdg_bound: bool,
     pub use_relaxed_wdg :bool,
+    /// Branch on the disjunctions that are fractional or expensive in the
+    /// WDG solution instead of by strong branching score, trying first the
+    /// alternative chosen by the solution. The children are still explored
+    /// in order of their bound, so the solution's choice only goes first
+    /// between children with equal bounds. Needs `use_wdg_bound`.
+    pub use_wdg_branching: bool,
+    /// Also compute the column generation bound of `colgen.rs`, and use the
+    /// larger of it and the WDG bound.
+    pub use_colgen_bound: bool,
+    /// Number of worker threads, each with its own `World`.
+    pub n_threads: usize,
+    /// Stop after solving this many nodes.
+    pub node_limit: Option<usize>,
+    /// Stop after this many `push_edge` calls, counting strong branching probes.
+    pub push_edge_limit: Option<u64>,
+    /// Stop after this many work units, i.e. edges scanned by the longest
+    /// path propagation. Time spent in the WDG MIP and in column generation
+    /// is not counted.
+    ///
+    /// Unlike the timeout, these limits give the same result on any machine
+    /// when running with a single thread.
+    pub work_limit: Option<u64>,
+    /// Record the search tree in `SolveResult::trace`. With restarts, the
+    /// trace covers the search after the last restart.
+    pub trace_search: bool,
+    /// Bound the number of nodes in the shared queue, to bound the memory
+    /// used by open nodes. When the queue holds this many nodes, workers stop
+    /// adding to it and continue depth-first on their own stack of children,
+    /// which is bounded by the tree depth, and only move as many nodes back
+    /// as the queue has room for. The limit counts nodes, not bytes: an open
+    /// node takes roughly 100 bytes, but also keeps its ancestors alive
+    /// through `Node::parent`, and those are not counted.
+    pub max_open_nodes: Option<usize>,
+    /// The heuristic modes ignore `n_threads`, `trace_search` and
+    /// `max_open_nodes`.
+    pub search_mode: SearchMode,
+    /// Restart the search after this many nodes, doubling the interval after
+    /// each restart. The incumbent, the lower bound and each world's
+    /// branching history are kept, so the conflict counts that weight the
+    /// strong-branching scores, and the pseudo-costs used with
+    /// `pseudo_cost_reliability`, carry over to the next round.
+    pub restart_nodes: Option<usize>,
+    /// Reliability branching: rank disjunctions by their pseudo-costs, the
+    /// average bound increase of the children created with each alternative,
+    /// once each alternative has this many observations, and only probe the
+    /// best of them. Has no effect with `use_wdg_bound` or `use_colgen_bound`,
+    /// which need every probe. See `World::pseudo_cost_reliability`.
+    pub pseudo_cost_reliability: Option<u32>,
+    /// Simplify the problem with `presolve::presolve` before the search.
+    pub presolve: bool,
+    /// Minimise the sum of the problem's objectives times these weights,
+    /// see `DisjunctiveGraph::objectives`. Empty means objective 0 only.
+    pub objective_weights: Vec<u32>,
+    /// Only accept solutions where each objective is at most its limit.
+    /// `multiobjective.rs` uses this for lexicographic and ε-constraint
+    /// solves.
+    pub objective_limits: Vec<Option<i32>>,
+}
+
+/// A world for `problem` set up for `settings`.
+fn new_world(problem: &DisjunctiveGraph, settings: &SolverSettings) -> Option<World> {
+    let mut world = World::new(problem)?;
+    world.pseudo_cost_reliability = settings.pseudo_cost_reliability.unwrap_or(0);
+    world.record_history = settings.restart_nodes.is_some() || world.pseudo_cost_reliability > 0;
+    if !settings.objective_weights.is_empty() || !settings.objective_limits.is_empty() {
+        let weights = if settings.objective_weights.is_empty() { &[1][..] } else { &settings.objective_weights };
+        if !world.set_objectives(weights, &settings.objective_limits) {
+            return None;
+        }
+    }
+    Some(world)
+}
+
+/// The limit that stopped the search early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Limit {
+    Time,
+    Nodes,
+    PushEdges,
+    WorkUnits,
+    Cancelled,
+}
+
+/// How often `Progress::Nodes` is reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Events reported to the progress callback of `solve_with_callback`.
+#[derive(Debug)]
+pub enum Progress<'a> {
+    /// A better solution was found.
+    NewIncumbent { objective: i32, positions: &'a [i32] },
+    /// The global lower bound increased.
+    BoundImproved { lower_bound: i32 },
+    /// Reported every `PROGRESS_INTERVAL`.
+    Nodes {
+        n_nodes_solved: usize,
+        n_open_nodes: usize,
+        lower_bound: i32,
+        upper_bound: i32,
+        elapsed: Duration,
+    },
+}
+
+/// Stops a running search from another thread. The search then returns its
+/// incumbent and bound as if a limit had been reached.
+#[derive(Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
 }
 
+/// A search tree node. The node's world state is rebuilt by pushing the
+/// edges on the path from the root, so any worker can pick up any node.
 #[derive(Default)]
-struct Node {
-    state: State,
-    depth: u32,
-    parent: Option<(Rc<Node>, Edge)>,
+pub(crate) struct Node {
+    /// Unique within a search; worker `i` of `n` numbers its nodes `i + k*n`.
+    pub(crate) id: usize,
+    pub(crate) state: State,
+    pub(crate) depth: u32,
+    pub(crate) parent: Option<(Arc<Node>, Edge)>,
 }
 
 impl PartialEq for Node {
@@ -46,6 +187,108 @@ impl Ord for Node {
     }
 }
 
+/// The best schedule found: its objective value and the time of every node.
+#[derive(Debug, Serialize)]
+pub struct Solution {
+    pub objective: i32,
+    pub positions: Vec<i32>,
+}
+
+/// How the search ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SolveStatus {
+    /// The search completed and the incumbent is optimal.
+    Optimal,
+    /// A limit was reached after finding a solution, or a heuristic search
+    /// mode found a solution, which may not be optimal.
+    Feasible,
+    /// The search completed without finding a solution.
+    Infeasible,
+    /// The time limit was reached before finding a solution.
+    Timeout,
+    /// A node, `push_edge` or work limit was reached before finding a solution.
+    NodeLimit,
+    /// The search was cancelled before finding a solution.
+    Cancelled,
+    /// A heuristic search mode found no solution. This does not mean that
+    /// there is none.
+    NoSolutionFound,
+}
+
+impl SolveStatus {
+    /// The status when `limit` stopped the search before finding a solution.
+    pub(crate) fn from_limit(limit: Limit) -> SolveStatus {
+        match limit {
+            Limit::Time => SolveStatus::Timeout,
+            Limit::Cancelled => SolveStatus::Cancelled,
+            Limit::Nodes | Limit::PushEdges | Limit::WorkUnits => SolveStatus::NodeLimit,
+        }
+    }
+}
+
+/// `(objective - lower_bound) / objective`, or infinite without a solution.
+pub(crate) fn relative_gap(objective: Option<i32>, lower_bound: i32) -> f64 {
+    match objective {
+        Some(objective) if objective > lower_bound => {
+            (objective - lower_bound) as f64 / objective as f64
+        }
+        Some(_) => 0.0,
+        None => f64::INFINITY,
+    }
+}
+
+/// The first limit in `settings` that has been reached, if any.
+pub(crate) fn check_limits(
+    settings: &SolverSettings,
+    deadline: Instant,
+    cancel: &CancelHandle,
+    n_nodes_solved: usize,
+    n_push_edge: u64,
+    work_units: u64,
+) -> Option<Limit> {
+    if cancel.is_cancelled() {
+        Some(Limit::Cancelled)
+    } else if Instant::now() > deadline {
+        Some(Limit::Time)
+    } else if settings.node_limit.is_some_and(|l| n_nodes_solved >= l) {
+        Some(Limit::Nodes)
+    } else if settings.push_edge_limit.is_some_and(|l| n_push_edge >= l) {
+        Some(Limit::PushEdges)
+    } else if settings.work_limit.is_some_and(|l| work_units >= l) {
+        Some(Limit::WorkUnits)
+    } else {
+        None
+    }
+}
+
+/// The outcome of `solve`. The lower bound and gap are valid whether or not
+/// the search completed.
+#[derive(Debug)]
+pub struct SolveResult {
+    pub status: SolveStatus,
+    pub stats: SolverStats,
+    pub solution: Option<Solution>,
+    /// The smallest lower bound of any open node, or the incumbent's value
+    /// if the search completed.
+    pub lower_bound: i32,
+    /// `(objective - lower_bound) / objective`, or infinite without a solution.
+    pub gap: f64,
+    pub trace: Option<SearchTrace>,
+}
+
+impl SolveResult {
+    fn infeasible(stats: SolverStats) -> Self {
+        SolveResult {
+            status: SolveStatus::Infeasible,
+            stats,
+            solution: None,
+            lower_bound: i32::MAX,
+            gap: f64::INFINITY,
+            trace: None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SolverStats {
     pub n_states_generated: usize,
@@ -56,109 +299,606 @@ pub struct SolverStats {
     pub root_bound: i32,
     pub best_bound: i32,
     pub best_value: i32,
+    pub n_push_edge: u64,
+    pub work_units: u64,
+    pub limit_reached: Option<Limit>,
+
+    /// Wall time summed over the workers.
+    pub time_propagation: Duration,
+    pub time_probing: Duration,
+    pub time_wdg_build: Duration,
+    pub time_highs: Duration,
+    /// Time spent taking nodes from and adding nodes to the shared queue,
+    /// including waiting for work.
+    pub time_queue: Duration,
+    pub n_highs_calls: usize,
+    pub n_dominated_disjunctions: usize,
+    pub n_wdg_cuts: usize,
+    pub time_colgen: Duration,
+    pub n_colgen_columns: usize,
+    /// The largest size of the shared queue.
+    pub peak_open_nodes: usize,
+    /// Nodes processed depth-first because the queue was at `max_open_nodes`.
+    pub n_depth_first_nodes: usize,
+    pub n_restarts: usize,
+    pub presolve: Option<PresolveStats>,
+}
+
+impl SolverStats {
+    pub(crate) fn new() -> Self {
+        SolverStats {
+            max_depth: 0,
+            n_nodes_generated: 0,
+            n_nodes_solved: 0,
+            n_states_generated: 0,
+            solution_depth: u32::MAX,
+            root_bound: 0,
+            best_bound: 0,
+            best_value: i32::MAX,
+            n_push_edge: 0,
+            work_units: 0,
+            limit_reached: None,
+            time_propagation: Duration::ZERO,
+            time_probing: Duration::ZERO,
+            time_wdg_build: Duration::ZERO,
+            time_highs: Duration::ZERO,
+            time_queue: Duration::ZERO,
+            n_highs_calls: 0,
+            n_dominated_disjunctions: 0,
+            n_wdg_cuts: 0,
+            time_colgen: Duration::ZERO,
+            n_colgen_columns: 0,
+            peak_open_nodes: 0,
+            n_depth_first_nodes: 0,
+            n_restarts: 0,
+            presolve: None,
+        }
+    }
+
+    /// Take the timings and WDG counters from a worker's world. These are
+    /// totals since the world was created, so with restarts they are taken
+    /// once after the last round.
+    pub(crate) fn add_timings(&mut self, world: &World) {
+        let wdg_stats = world.wdg_stats();
+        self.time_propagation = world.time_propagation;
+        self.time_probing = world.time_probing;
+        self.time_wdg_build = wdg_stats.build_time;
+        self.time_highs = wdg_stats.highs_time;
+        self.n_highs_calls = wdg_stats.n_highs_calls;
+        self.n_dominated_disjunctions = wdg_stats.n_dominated_disjunctions;
+        self.n_wdg_cuts = wdg_stats.n_cuts;
+        let colgen_stats = world.colgen_stats();
+        self.time_colgen = colgen_stats.time;
+        self.n_colgen_columns = colgen_stats.n_columns;
+    }
+
+    /// Add up the counters of one worker, or of one of several solves.
+    pub(crate) fn merge(&mut self, other: &SolverStats) {
+        self.n_states_generated += other.n_states_generated;
+        self.n_nodes_generated += other.n_nodes_generated;
+        self.n_nodes_solved += other.n_nodes_solved;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.time_propagation += other.time_propagation;
+        self.time_probing += other.time_probing;
+        self.time_wdg_build += other.time_wdg_build;
+        self.time_highs += other.time_highs;
+        self.time_queue += other.time_queue;
+        self.n_highs_calls += other.n_highs_calls;
+        self.n_dominated_disjunctions += other.n_dominated_disjunctions;
+        self.n_wdg_cuts += other.n_wdg_cuts;
+        self.time_colgen += other.time_colgen;
+        self.n_colgen_columns += other.n_colgen_columns;
+        self.n_depth_first_nodes += other.n_depth_first_nodes;
+    }
+}
+
+/// Search state shared between the workers.
+struct Shared<'a> {
+    queue_by_lb: BinaryHeap<Reverse<Arc<Node>>>,
+    best: Option<(Arc<Node>, Vec<i32>)>,
+    /// The lower bound of the node each worker is working on, including the
+    /// nodes on its depth-first stack, or `i32::MAX` when the worker is
+    /// waiting for work.
+    working_lb: Vec<i32>,
+    peak_open_nodes: usize,
+    n_idle: usize,
+    best_bound: i32,
+    n_nodes_solved: usize,
+    n_push_edge: u64,
+    work_units: u64,
+    done: bool,
+    limit_reached: Option<Limit>,
+    /// Stop for a restart when `n_nodes_solved` reaches this.
+    restart_at: Option<usize>,
+    restart: bool,
+    cancel: CancelHandle,
+    /// Called with the lock held, so events arrive one at a time.
+    progress: &'a (dyn Fn(Progress) + Sync),
+    start_time: Instant,
+    last_progress: Instant,
+}
+
+impl Shared<'_> {
+    fn ub(&self) -> i32 {
+        self.best.as_ref().map(|(n, _)| n.state.lb).unwrap_or(i32::MAX)
+    }
+
+    /// The smallest lower bound of any open or in-progress node.
+    fn global_bound(&self) -> i32 {
+        let queue_lb = self
+            .queue_by_lb
+            .peek()
+            .map(|n| n.0.state.lb)
+            .unwrap_or(i32::MAX);
+        let working_lb = self.working_lb.iter().copied().min().unwrap_or(i32::MAX);
+        queue_lb.min(working_lb).min(self.ub())
+    }
+
+    /// Stop the search if any of the limits has been reached.
+    fn check_limits(&mut self, settings: &SolverSettings, deadline: Instant) {
+        let limit = check_limits(
+            settings,
+            deadline,
+            &self.cancel,
+            self.n_nodes_solved,
+            self.n_push_edge,
+            self.work_units,
+        );
+        if limit.is_some() && !self.done {
+            self.limit_reached = limit;
+            self.done = true;
+        }
+        if self.restart_at.is_some_and(|r| self.n_nodes_solved >= r) && !self.done {
+            self.restart = true;
+            self.done = true;
+        }
+    }
+
+    fn report_nodes(&mut self) {
+        if self.last_progress.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        self.last_progress = Instant::now();
+        (self.progress)(Progress::Nodes {
+            n_nodes_solved: self.n_nodes_solved,
+            n_open_nodes: self.queue_by_lb.len(),
+            lower_bound: self.best_bound,
+            upper_bound: self.ub(),
+            elapsed: self.start_time.elapsed(),
+        });
+    }
 }
 
 pub fn solve(
     problem: &DisjunctiveGraph,
     settings: &SolverSettings,
     timeout: Duration,
-) -> (SolverStats, Option<i32>) {
+) -> SolveResult {
+    solve_with_callback(problem, settings, timeout, None, &|_| {}, &CancelHandle::default())
+}
+
+/// Like `solve`, but starts from `initial_solution` if it is a complete and
+/// feasible solution, reports progress to `progress` and stops early when
+/// `cancel` is cancelled. The callback runs on the worker threads and
+/// should return quickly.
+///
+/// To keep parts of a plan fixed instead, solve `problem.with_fixed(..)`.
+pub fn solve_with_callback(
+    problem: &DisjunctiveGraph,
+    settings: &SolverSettings,
+    timeout: Duration,
+    initial_solution: Option<&Assignment>,
+    progress: &(dyn Fn(Progress) + Sync),
+    cancel: &CancelHandle,
+) -> SolveResult {
     let start_time = Instant::now();
-    let mut stats = SolverStats {
-        max_depth: 0,
-        n_nodes_generated: 0,
-        n_nodes_solved: 0,
-        n_states_generated: 0,
-        solution_depth: u32::MAX,
-        root_bound: 0,
-        best_bound: 0,
-        best_value: i32::MAX,
-    };
-    let mut world = match World::new(problem) {
-        None => {
-            return (stats, None);
+    let mut stats = SolverStats::new();
+
+    // The initial solution's choices refer to the edge sets of `original`.
+    let original = problem;
+    let presolved = settings.presolve.then(|| presolve(original));
+    let problem = match presolved.as_ref() {
+        None => original,
+        Some(None) => return SolveResult::infeasible(stats),
+        Some(Some((problem, presolve_stats))) => {
+            stats.presolve = Some(*presolve_stats);
+            problem
         }
+    };
+    let mut world = match new_world(problem, settings) {
+        None => return SolveResult::infeasible(stats),
         Some(w) => w,
     };
+    let n_multiway = world.n_multiway_disjunctions();
+    if settings.use_wdg_bound && n_multiway > 0 {
+        warn!(
+            "{} disjunctions with more than two alternatives are left out of the WDG bound",
+            n_multiway
+        );
+    }
+
+    // Check the initial solution and use it as the first incumbent.
+    let initial_best = initial_solution.and_then(|a| {
+        let edges = original
+            .chosen_edges(a)
+            .into_iter()
+            .map(|(_, e)| e)
+            .collect::<Vec<_>>();
+        let best = world.try_solution(&edges);
+        if best.is_none() {
+            warn!("initial solution is incomplete or infeasible, ignoring it");
+        }
+        best
+    });
+    let ub = initial_best.as_ref().map(|(obj, _)| *obj).unwrap_or(i32::MAX);
 
     // let root_longestpath_bound = world.longestpaths_bound();
-    let mut world_state = Rc::new(Node {
+    // The root state is `None` when the initial solution is already optimal.
+    let root = Arc::new(Node {
+        id: 0,
         state: world
-            .mk_state(settings, world.longestpaths_bound(), i32::MAX)
-            .unwrap(),
+            .mk_state(settings, world.longestpaths_bound(), ub)
+            .unwrap_or(State {
+                lb: ub,
+                branching: None,
+            }),
         depth: 0,
         parent: None,
     });
-    stats.root_bound = world_state.state.lb;
-    debug!("Root node state {:?}", world_state.state);
-    let mut target_state = world_state.clone();
-    let mut queue_by_lb: BinaryHeap<Reverse<Rc<Node>>> = Default::default();
-    let mut best_state: Option<Rc<Node>> = None;
-    let mut node_buf: Vec<Rc<Node>> = Vec::new();
+    stats.root_bound = root.state.lb;
+    debug!("Root node state {:?}", root.state);
 
-    loop {
+    if settings.search_mode != SearchMode::BestFirst {
+        if let Some((objective, positions)) = initial_best.as_ref() {
+            progress(Progress::NewIncumbent {
+                objective: *objective,
+                positions,
+            });
+        }
+        let search = HeuristicSearch {
+            world,
+            world_state: root.clone(),
+            node_buf: Vec::new(),
+            settings,
+            stats,
+            best: initial_best.map(|(objective, positions)| (objective, 0, positions)),
+            progress,
+            cancel,
+            deadline: start_time + timeout,
+            limit_reached: None,
+        };
+        return search.run(&root);
+    }
+    let n_threads = settings.n_threads.max(1);
+    let deadline = start_time + timeout;
+    let mut best = initial_best.map(|(objective, positions)| {
+        info!("INITIAL {}", objective);
+        progress(Progress::NewIncumbent {
+            objective,
+            positions: &positions,
+        });
+        // Not part of the search tree.
+        let node = Arc::new(Node {
+            id: usize::MAX,
+            state: State {
+                lb: objective,
+                branching: None,
+            },
+            depth: 0,
+            parent: None,
+        });
+        (node, positions)
+    });
+    let mut root = root;
+    let mut best_bound = 0;
+    let mut counters = (0, world.n_push_edge(), world.work_units());
+    let mut peak_open_nodes = 1;
+    let mut restart_interval = settings.restart_nodes;
+    let mut recorders = Vec::new();
 
+    // The first worker reuses the root's world, the others build their own.
+    // Worlds are kept across restarts, along with their branching history.
+    let mut worlds = vec![world];
+    let shared = loop {
+        recorders.clear();
+        if settings.trace_search {
+            let mut recorder = TraceRecorder::default();
+            recorder.nodes.push(TraceNode {
+                id: 0,
+                parent: None,
+                depth: 0,
+                lb: Some(root.state.lb),
+                edge: None,
+                status: NodeStatus::Open,
+                processed_at: None,
+            });
+            recorders.push(recorder);
+        }
 
+        let shared = (
+            Mutex::new(Shared {
+                queue_by_lb: [Reverse(root.clone())].into_iter().collect(),
+                best: best.take(),
+                working_lb: vec![i32::MAX; n_threads],
+                peak_open_nodes,
+                n_idle: 0,
+                best_bound,
+                n_nodes_solved: counters.0,
+                n_push_edge: counters.1,
+                work_units: counters.2,
+                done: false,
+                limit_reached: None,
+                restart_at: restart_interval.map(|i| counters.0 + i),
+                restart: false,
+                cancel: cancel.clone(),
+                progress,
+                start_time,
+                last_progress: start_time,
+            }),
+            Condvar::new(),
+        );
 
-        // Terminate timeout
-        if start_time.elapsed() > timeout {
-            return (stats, None);
-        }
+        std::thread::scope(|scope| {
+            let workers = (0..n_threads)
+                .map(|worker_idx| {
+                    let world = worlds.pop();
+                    let (root, shared) = (&root, &shared);
+                    scope.spawn(move || {
+                        let world = world.unwrap_or_else(|| new_world(problem, settings).unwrap());
+                        let counted = (world.n_push_edge(), world.work_units());
+                        worker(worker_idx, world, counted, root, settings, shared, deadline)
+                    })
+                })
+                .collect::<Vec<_>>();
+            for w in workers {
+                let (worker_stats, recorder, world) = w.join().unwrap();
+                stats.merge(&worker_stats);
+                recorders.extend(recorder);
+                worlds.push(world);
+            }
+        });
 
-        // Terminate when lb >= ub
-        if target_state.state.lb >= best_state.as_ref().map(|s| s.state.lb).unwrap_or(i32::MAX) {
-            break;
+        let shared = shared.0.into_inner().unwrap();
+        if !shared.restart {
+            break shared;
         }
 
-        stats.best_bound = stats.best_bound.max(target_state.state.lb);
+        // Restart from the root with what was learned so far.
+        stats.n_restarts += 1;
+        best_bound = shared.best_bound.max(shared.global_bound());
+        peak_open_nodes = shared.peak_open_nodes;
+        restart_interval = restart_interval.map(|i| 2 * i);
+        best = shared.best;
+        let ub = best.as_ref().map(|(n, _)| n.state.lb).unwrap_or(i32::MAX);
+        info!("RESTART {} lb={} ub={}", stats.n_restarts, best_bound, ub);
 
-        // Bring the world state to the target state:
-        //
-        // Pop constraints until we reach the common ancestor,
-        // then push constraints until we get down to the `node`.
-        trace!(
-            "going to node d={} lb={}",
-            target_state.depth,
-            target_state.state.lb
+        let world = &mut worlds[0];
+        let (push_edge_before, work_before) = (world.n_push_edge(), world.work_units());
+        root = Arc::new(Node {
+            id: 0,
+            state: world
+                .mk_state(settings, world.longestpaths_bound(), ub)
+                .unwrap_or(State {
+                    lb: ub,
+                    branching: None,
+                }),
+            depth: 0,
+            parent: None,
+        });
+        counters = (
+            shared.n_nodes_solved,
+            shared.n_push_edge + world.n_push_edge() - push_edge_before,
+            shared.work_units + world.work_units() - work_before,
         );
-        {
-            let mut common_ancestor = &target_state;
-            while !Rc::ptr_eq(&world_state, common_ancestor) {
-                if common_ancestor.depth > world_state.depth {
-                    node_buf.push(common_ancestor.clone());
-                    common_ancestor = &common_ancestor.parent.as_ref().unwrap().0;
+    };
+
+    for world in worlds.iter() {
+        let mut world_stats = SolverStats::new();
+        world_stats.add_timings(world);
+        stats.merge(&world_stats);
+    }
+
+    // Nodes left in the queue or unfinished by a worker when a limit stopped
+    // the search still bound the optimum from below.
+    let lower_bound = shared.best_bound.max(shared.global_bound());
+    stats.best_bound = lower_bound;
+    stats.n_push_edge = shared.n_push_edge;
+    stats.work_units = shared.work_units;
+    stats.limit_reached = shared.limit_reached;
+    stats.peak_open_nodes = shared.peak_open_nodes;
+    stats.solution_depth = shared.best.as_ref().map(|(n, _)| n.depth).unwrap_or(u32::MAX);
+    stats.best_value = shared.best.as_ref().map(|(n, _)| n.state.lb).unwrap_or(i32::MAX);
+
+    let status = match (shared.limit_reached, shared.best.is_some()) {
+        (None, true) => SolveStatus::Optimal,
+        (None, false) => SolveStatus::Infeasible,
+        (Some(_), true) => SolveStatus::Feasible,
+        (Some(limit), false) => SolveStatus::from_limit(limit),
+    };
+    let gap = relative_gap(shared.best.as_ref().map(|(n, _)| n.state.lb), lower_bound);
+
+    let trace = settings
+        .trace_search
+        .then(|| SearchTrace::merge(recorders, shared.ub()));
+
+    SolveResult {
+        status,
+        stats,
+        solution: shared.best.map(|(n, positions)| Solution {
+            objective: n.state.lb,
+            positions,
+        }),
+        lower_bound,
+        gap,
+        trace,
+    }
+}
+
+/// Process nodes until the search is done. Each worker dives into the best
+/// child of its node as long as that child is at least as good as the best
+/// open node, and otherwise takes work from the shared queue.
+fn worker(
+    worker_idx: usize,
+    mut world: World,
+    mut counted: (u64, u64),
+    root: &Arc<Node>,
+    settings: &SolverSettings,
+    (shared, work_available): &(Mutex<Shared<'_>>, Condvar),
+    deadline: Instant,
+) -> (SolverStats, Option<TraceRecorder>, World) {
+    let mut stats = SolverStats::new();
+    let n_threads = settings.n_threads.max(1);
+    let mut next_id = worker_idx + n_threads;
+    let mut recorder = settings.trace_search.then(TraceRecorder::default);
+    let start_time = shared.lock().unwrap().start_time;
+    let mut world_state = root.clone();
+    let mut node_buf: Vec<Arc<Node>> = Vec::new();
+    let mut new_nodes: TinyVec<[Arc<Node>; 2]> = Default::default();
+    // Children kept out of the full shared queue, best last.
+    let mut depth_first_stack: Vec<Arc<Node>> = Vec::new();
+    let mut new_best: Option<(Arc<Node>, Vec<i32>)> = None;
+
+    loop {
+        // Report the results of the previous node and find the next node to process,
+        // using the shared queue only if necessary.
+        let queue_start = Instant::now();
+        let (target_state, ub) = {
+            let mut shared = shared.lock().unwrap();
+            if let Some((node, positions)) = new_best.take() {
+                if node.state.lb < shared.ub() {
+                    info!("NEW BEST {}", node.state.lb);
+                    (shared.progress)(Progress::NewIncumbent {
+                        objective: node.state.lb,
+                        positions: &positions,
+                    });
+                    shared.best = Some((node, positions));
+                }
+            }
+
+            // Add this worker's work since the last report, and terminate on limits.
+            shared.n_push_edge += world.n_push_edge() - counted.0;
+            shared.work_units += world.work_units() - counted.1;
+            counted = (world.n_push_edge(), world.work_units());
+            shared.check_limits(settings, deadline);
+            if shared.done {
+                work_available.notify_all();
+                break;
+            }
+
+            // Drop nodes with lb >= ub
+            let ub = shared.ub();
+            new_nodes.retain(|n| n.state.lb < ub);
+            depth_first_stack.retain(|n| n.state.lb < ub);
+
+            let room = settings
+                .max_open_nodes
+                .map_or(usize::MAX, |m| m.saturating_sub(shared.queue_by_lb.len()));
+            let next = if room == 0 {
+                // Depth-first: continue with the best child, or backtrack.
+                // The sort is stable, so children with equal bounds stay in
+                // branching order.
+                new_nodes.sort_by_key(|n| n.state.lb);
+                depth_first_stack.extend(new_nodes.drain(..).rev());
+                let next = depth_first_stack.pop();
+                stats.n_depth_first_nodes += next.is_some() as usize;
+                next
+            } else {
+                // Nodes left over from depth-first search go back to the
+                // queue, the best first, as far as it has room. The rest stay
+                // on the stack, and depth-first search continues with them.
+                new_nodes.extend(depth_first_stack.drain(..));
+                new_nodes.sort_by_key(|n| n.state.lb);
+                let next = if !new_nodes.is_empty()
+                    && new_nodes[0].state.lb
+                        <= shared.queue_by_lb.peek().map(|q| q.0.state.lb).unwrap_or(i32::MAX)
+                {
+                    Some(new_nodes.remove(0))
                 } else {
-                    world.pop();
-                    world_state = world_state.parent.as_ref().unwrap().0.clone();
+                    None
+                };
+                if new_nodes.len() > room {
+                    depth_first_stack.extend(new_nodes.drain(room..).rev());
                 }
+                match next {
+                    None if !depth_first_stack.is_empty() => {
+                        stats.n_depth_first_nodes += 1;
+                        depth_first_stack.pop()
+                    }
+                    next => next,
+                }
+            };
+            if !new_nodes.is_empty() {
+                shared.queue_by_lb.extend(new_nodes.drain(..).map(Reverse));
+                shared.peak_open_nodes = shared.peak_open_nodes.max(shared.queue_by_lb.len());
+                work_available.notify_all();
             }
-            for n in node_buf.drain(..).rev() {
-                assert!(world.push(n.parent.as_ref().unwrap().1));
-                world_state = n;
+
+            let (mut shared, next) = match next {
+                Some(n) => (shared, Some(n)),
+                None => next_open(shared, worker_idx, work_available, settings, deadline),
+            };
+            let Some(next) = next else {
+                break;
+            };
+            shared.working_lb[worker_idx] = depth_first_stack
+                .iter()
+                .map(|n| n.state.lb)
+                .fold(next.state.lb, i32::min);
+            shared.n_nodes_solved += 1;
+            let global_bound = shared.global_bound();
+            if global_bound > shared.best_bound {
+                shared.best_bound = global_bound;
+                (shared.progress)(Progress::BoundImproved {
+                    lower_bound: global_bound,
+                });
             }
-        }
-        assert!(Rc::ptr_eq(&target_state, &world_state));
+            shared.report_nodes();
+            let ub = shared.ub();
+            (next, ub)
+        };
+        stats.time_queue += queue_start.elapsed();
+
+        go_to(&mut world, &mut world_state, &target_state, &mut node_buf);
         stats.n_nodes_solved += 1;
+        if let Some(r) = recorder.as_mut() {
+            r.processed
+                .push((target_state.id, start_time.elapsed().as_secs_f64()));
+        }
 
         // Generate new nodes based on the target node's precomputed branching choices.
         //
-        let ub = best_state.as_ref().map(|n| n.state.lb).unwrap_or(i32::MAX);
-        let mut new_nodes: TinyVec<[Rc<Node>; 2]> = match target_state.state.branching.as_ref() {
+        match target_state.state.branching.as_ref() {
             None => {
-                if target_state.state.lb < ub {
-                    info!("NEW BEST {}", target_state.state.lb);
-                    stats.best_value = target_state.state.lb;
-                    best_state = Some(target_state);
-                }
-                Default::default()
+                new_best = Some((target_state.clone(), world.positions()));
             }
             Some(bs) => {
-                let mut new_nodes: TinyVec<[Rc<Node>; 2]> = Default::default();
                 for b in bs.iter() {
-                    assert!(world.push(*b));
+                    // Alternatives that were not probed can be infeasible.
+                    if !world.push(*b) {
+                        continue;
+                    }
                     let state = world.mk_state(settings, target_state.state.lb, ub);
                     stats.n_states_generated += 1;
+                    let id = next_id;
+                    next_id += n_threads;
+                    if let Some(r) = recorder.as_mut() {
+                        r.nodes.push(TraceNode {
+                            id,
+                            parent: Some(target_state.id),
+                            depth: target_state.depth + 1,
+                            lb: state.as_ref().map(|s| s.lb),
+                            edge: Some(*b),
+                            status: match state.as_ref().map(|s| s.branching.as_ref()) {
+                                None => NodeStatus::Pruned,
+                                Some(None) => NodeStatus::Solution,
+                                Some(Some(b)) if b.is_empty() => NodeStatus::Infeasible,
+                                Some(Some(_)) => NodeStatus::Open,
+                            },
+                            processed_at: None,
+                        });
+                    }
                     if let Some(state) = state {
+                        world.record_branching(*b, state.lb - target_state.state.lb);
                         if !state
                             .branching
                             .as_ref()
@@ -166,7 +906,8 @@ pub fn solve(
                             .unwrap_or(false)
                         {
                             stats.n_nodes_generated += 1;
-                            let node = Rc::new(Node {
+                            let node = Arc::new(Node {
+                                id,
                                 state,
                                 depth: target_state.depth + 1,
                                 parent: Some((target_state.clone(), *b)),
@@ -174,9 +915,9 @@ pub fn solve(
                             stats.max_depth = stats.max_depth.max(node.depth);
                             if node.state.branching.is_none() {
                                 assert!(node.state.lb < ub);
-                                info!("NEW BEST {}", node.state.lb);
-                                stats.best_value = target_state.state.lb;
-                                best_state = Some(node);
+                                if new_best.as_ref().map(|(n, _)| node.state.lb < n.state.lb).unwrap_or(true) {
+                                    new_best = Some((node, world.positions()));
+                                }
                             } else {
                                 new_nodes.push(node);
                             }
@@ -184,42 +925,85 @@ pub fn solve(
                     }
                     world.pop();
                 }
-
-                new_nodes
             }
-        };
+        }
+    }
 
-        // Find the next node to process, using the best-first queue only if necessary.
-        new_nodes.sort_by_key(|n| n.state.lb);
-        if !new_nodes.is_empty()
-            && new_nodes.first().map(|n| n.state.lb).unwrap_or(i32::MAX)
-                <= queue_by_lb.peek().map(|q| q.0.state.lb).unwrap_or(i32::MAX)
-        {
-            target_state = new_nodes.remove(0);
-        } else {
-            match queue_by_lb.pop() {
-                None => {
-                    debug!("queue empty");
-                    break;
-                }
-                Some(Reverse(n)) => {
-                    if n.state.lb >= ub {
-                        debug!("ub reached");
-                        break;
-                    }
-                    target_state = n;
-                }
+    // Leave the world at the root, ready for a restart.
+    go_to(&mut world, &mut world_state, root, &mut node_buf);
+    (stats, recorder, world)
+}
+
+/// Take the best node from the shared queue, waiting for other workers to
+/// produce nodes if the queue is empty. Returns `None` when the search is done.
+fn next_open<'a, 'b>(
+    mut shared: MutexGuard<'a, Shared<'b>>,
+    worker_idx: usize,
+    work_available: &Condvar,
+    settings: &SolverSettings,
+    deadline: Instant,
+) -> (MutexGuard<'a, Shared<'b>>, Option<Arc<Node>>) {
+    shared.working_lb[worker_idx] = i32::MAX;
+    shared.n_idle += 1;
+    loop {
+        shared.check_limits(settings, deadline);
+        if shared.done {
+            work_available.notify_all();
+            return (shared, None);
+        }
+
+        let ub = shared.ub();
+        match shared.queue_by_lb.pop() {
+            Some(Reverse(n)) if n.state.lb < ub => {
+                shared.n_idle -= 1;
+                return (shared, Some(n));
+            }
+            Some(_) => {
+                debug!("ub reached");
+                shared.queue_by_lb.clear();
+            }
+            None if shared.n_idle == shared.working_lb.len() => {
+                debug!("queue empty");
+                shared.done = true;
+            }
+            None => {
+                shared = work_available
+                    .wait_timeout(shared, Duration::from_millis(10))
+                    .unwrap()
+                    .0;
             }
         }
-        queue_by_lb.extend(new_nodes.into_iter().map(Reverse));
     }
+}
 
-    stats.solution_depth = best_state.as_ref().map(|n| n.depth).unwrap_or(u32::MAX);
-    
-    if let Some(best) = best_state.as_ref() {
-        stats.best_bound = stats.best_bound.max(best.state.lb);
-        stats.best_value = best.state.lb;
+/// Bring the world state to the target state:
+///
+/// Pop constraints until we reach the common ancestor,
+/// then push constraints until we get down to the `node`.
+pub(crate) fn go_to(
+    world: &mut World,
+    world_state: &mut Arc<Node>,
+    target_state: &Arc<Node>,
+    node_buf: &mut Vec<Arc<Node>>,
+) {
+    trace!(
+        "going to node d={} lb={}",
+        target_state.depth,
+        target_state.state.lb
+    );
+    let mut common_ancestor = target_state;
+    while !Arc::ptr_eq(world_state, common_ancestor) {
+        if common_ancestor.depth > world_state.depth {
+            node_buf.push(common_ancestor.clone());
+            common_ancestor = &common_ancestor.parent.as_ref().unwrap().0;
+        } else {
+            world.pop();
+            *world_state = world_state.parent.as_ref().unwrap().0.clone();
+        }
     }
-
-    (stats, best_state.map(|n| n.state.lb))
+    for n in node_buf.drain(..).rev() {
+        assert!(world.push(n.parent.as_ref().unwrap().1));
+        *world_state = n;
+    }
+    assert!(Arc::ptr_eq(target_state, world_state));
 }
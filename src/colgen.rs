@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use log::{trace, warn};
+
+use crate::{longestpaths::LongestPaths, problem::Edge, wdg::WdgEdge};
+
+/// Column generation rounds per bound.
+const MAX_ITERATIONS: usize = 30;
+/// `push_edge` calls allowed when pricing one partition in one round.
+const PRICING_PUSH_LIMIT: usize = 2000;
+/// Cost of the artificial column that keeps the master feasible.
+const ARTIFICIAL_COST: f64 = 1e7;
+
+/// Counters accumulated over all solves.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ColGenStats {
+    pub n_iterations: usize,
+    pub n_columns: usize,
+    pub time: Duration,
+}
+
+/// A lower bound from a Dantzig-Wolfe decomposition by partition.
+///
+/// Each partition chooses alternatives for the disjunctions that affect it,
+/// and pays the delay of its own nodes when all the chosen edges are pushed
+/// together. Unlike the WDG bound, which takes the largest delay of any
+/// single disjunction, this sees delays that add up along a train's route
+/// and knock-on delays through other trains. A restricted master LP makes
+/// the partitions agree on the choices, and new columns are priced by a
+/// depth-first search over the partition's choices using `LongestPaths`.
+///
+/// The bound is the Lagrangian bound given by the master's duals, which is
+/// valid in every round, so the iterations can stop at any time.
+#[derive(Default)]
+pub struct ColumnGenerationBound {
+    /// The alternatives of each disjunction. Choosing the first is `x = 1`.
+    disjunctions: Vec<[Edge; 2]>,
+    /// For each partition, the disjunctions that affect it.
+    partition_disjunctions: HashMap<u32, Vec<usize>>,
+    pub stats: ColGenStats,
+}
+
+/// Depth-first search for the column with the smallest reduced cost.
+struct Pricing<'a, F> {
+    schedule: &'a mut LongestPaths,
+    partition: &'a F,
+    target: u32,
+    edges: Vec<[Edge; 2]>,
+    duals: Vec<f64>,
+    /// The smallest possible sum of `-dual * x` from each level on.
+    rest: Vec<f64>,
+    x: Vec<bool>,
+    best: (f64, Vec<bool>),
+    /// The smallest bound of the subtrees skipped because of the push limit.
+    skipped: f64,
+    n_push: usize,
+}
+
+impl<F: Fn(u32) -> u32> Pricing<'_, F> {
+    fn search(&mut self, level: usize, cost: i64, value: f64) {
+        let bound = cost as f64 + value + self.rest[level];
+        if bound >= self.best.0 {
+            return;
+        }
+        if level == self.edges.len() {
+            self.best = (bound, self.x.clone());
+            return;
+        }
+        if self.n_push >= PRICING_PUSH_LIMIT {
+            self.skipped = self.skipped.min(bound);
+            return;
+        }
+
+        let first = self.duals[level] > 0.0;
+        for choose_first in [first, !first] {
+            let edge = self.edges[level][if choose_first { 0 } else { 1 }];
+            let (partition, target) = (self.partition, self.target);
+            let mut delay = 0;
+            self.n_push += 1;
+            if !self.schedule.push_edge(edge, |node, d_cost| {
+                if partition(node) == target {
+                    delay += d_cost as i64;
+                }
+            }) {
+                continue;
+            }
+            self.x[level] = choose_first;
+            let dual = if choose_first { self.duals[level] } else { 0.0 };
+            self.search(level + 1, cost + delay, value - dual);
+            self.schedule.pop(|_| {});
+        }
+    }
+}
+
+impl ColumnGenerationBound {
+    pub fn clear(&mut self) {
+        self.disjunctions.clear();
+        self.partition_disjunctions.clear();
+    }
+
+    /// Add a disjunction with the delays `alt1` and `alt2` found by probing
+    /// each alternative. It is only priced for the partitions delayed by
+    /// either of them.
+    pub fn add_disjunction(&mut self, alternatives: [Edge; 2], alt1: &[WdgEdge], alt2: &[WdgEdge]) {
+        let d_idx = self.disjunctions.len();
+        self.disjunctions.push(alternatives);
+        let mut partitions = alt1.iter().chain(alt2.iter()).map(|e| e.partition).collect::<Vec<_>>();
+        partitions.sort_unstable();
+        partitions.dedup();
+        for p in partitions {
+            self.partition_disjunctions.entry(p).or_default().push(d_idx);
+        }
+    }
+
+    /// The bound on the total delay, given the current `schedule` and the
+    /// partition of each node. Returns `i32::MAX` if the choices cannot be
+    /// made consistently for some partition.
+    ///
+    /// The pricing pushes are not counted in the schedule's `n_push_edge`
+    /// and `n_edges_scanned`, so like the WDG MIP they do not use up the
+    /// `push_edge_limit` and `work_limit`.
+    pub fn solve(&mut self, schedule: &mut LongestPaths, partition: impl Fn(u32) -> u32) -> i32 {
+        if self.disjunctions.is_empty() {
+            return 0;
+        }
+        let start = Instant::now();
+        let counters = (schedule.n_push_edge, schedule.n_edges_scanned);
+        let bound = self.solve_master(schedule, partition);
+        (schedule.n_push_edge, schedule.n_edges_scanned) = counters;
+        self.stats.time += start.elapsed();
+        bound
+    }
+
+    fn solve_master(&mut self, schedule: &mut LongestPaths, partition: impl Fn(u32) -> u32) -> i32 {
+
+        let mut partitions = self
+            .partition_disjunctions
+            .iter()
+            .map(|(p, ds)| (*p, ds.clone()))
+            .collect::<Vec<_>>();
+        partitions.sort_unstable_by_key(|(p, _)| *p);
+
+        // Master: choose a convex combination of columns for each partition,
+        // such that the partitions agree on each disjunction's `y`.
+        // Rows are kept with their index, to look up their duals.
+        let mut master = highs::ColProblem::default();
+        let mut n_rows = 0;
+        let mut add_row = |master: &mut highs::ColProblem, value: f64| {
+            n_rows += 1;
+            (master.add_row(value..=value), n_rows - 1)
+        };
+        let convexity = partitions
+            .iter()
+            .map(|_| add_row(&mut master, 1.0))
+            .collect::<Vec<_>>();
+        let linking = partitions
+            .iter()
+            .map(|(_, ds)| ds.iter().map(|_| add_row(&mut master, 0.0)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let mut y_rows: Vec<Vec<(highs::Row, usize)>> = vec![Vec::new(); self.disjunctions.len()];
+        for (p_idx, (_, ds)) in partitions.iter().enumerate() {
+            for (j, d) in ds.iter().enumerate() {
+                y_rows[*d].push(linking[p_idx][j]);
+            }
+        }
+        for rows in y_rows.iter() {
+            master.add_column(0.0, 0.0..=1.0, rows.iter().map(|(r, _)| (*r, -1.0)));
+        }
+        let mut column_costs = vec![0.0; y_rows.len()];
+        for (row, _) in convexity.iter() {
+            master.add_column(ARTIFICIAL_COST, 0.0.., [(*row, 1.0)]);
+            column_costs.push(ARTIFICIAL_COST);
+        }
+
+        let mut model = master.optimise(highs::Sense::Minimise);
+        model.make_quiet();
+        let mut best_bound = 0.0f64;
+        for _ in 0..MAX_ITERATIONS {
+            self.stats.n_iterations += 1;
+            let solved = model.solve();
+            if solved.status() != highs::HighsModelStatus::Optimal {
+                // The bound from the earlier rounds is still valid.
+                warn!("column generation master status {:?}", solved.status());
+                break;
+            }
+            let solution = solved.get_solution();
+            let duals = solution.dual_rows();
+            let convexity_dual = |p_idx: usize| duals[convexity[p_idx].1];
+            let linking_dual = |p_idx: usize, j: usize| duals[linking[p_idx][j].1];
+
+            // The Lagrangian bound for these duals: each partition's smallest
+            // reduced cost without its convexity dual, plus the best `y`.
+            let mut lagrangian = 0.0;
+            for rows in y_rows.iter() {
+                let y_coeff: f64 = rows.iter().map(|(_, r)| duals[*r]).sum();
+                lagrangian += y_coeff.min(0.0);
+            }
+
+            let mut new_columns = Vec::new();
+            for (p_idx, (p, ds)) in partitions.iter().enumerate() {
+                let duals = (0..ds.len()).map(|j| linking_dual(p_idx, j)).collect::<Vec<_>>();
+                let mut rest = vec![0.0; ds.len() + 1];
+                for j in (0..ds.len()).rev() {
+                    rest[j] = rest[j + 1] + (-duals[j]).min(0.0);
+                }
+                let mut pricing = Pricing {
+                    schedule: &mut *schedule,
+                    partition: &partition,
+                    target: *p,
+                    edges: ds.iter().map(|d| self.disjunctions[*d]).collect(),
+                    duals,
+                    rest,
+                    x: vec![false; ds.len()],
+                    best: (f64::INFINITY, Vec::new()),
+                    skipped: f64::INFINITY,
+                    n_push: 0,
+                };
+                pricing.search(0, 0, 0.0);
+                let subproblem_bound = pricing.best.0.min(pricing.skipped);
+                if subproblem_bound == f64::INFINITY {
+                    return i32::MAX;
+                }
+                lagrangian += subproblem_bound;
+
+                let (value, x) = pricing.best;
+                if value < convexity_dual(p_idx) - 1e-6 {
+                    let cost = value + x.iter().zip(pricing.duals.iter()).map(|(x, d)| if *x { *d } else { 0.0 }).sum::<f64>();
+                    new_columns.push((p_idx, cost.round(), x));
+                }
+            }
+            best_bound = best_bound.max(lagrangian);
+            trace!(
+                "colgen master {} lagrangian {} new columns {}",
+                solution.columns().iter().zip(column_costs.iter()).map(|(x, c)| x * c).sum::<f64>(),
+                lagrangian,
+                new_columns.len()
+            );
+
+            if new_columns.is_empty() {
+                break;
+            }
+            model = solved.into();
+            for (p_idx, cost, x) in new_columns {
+                let rows = x
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, x)| **x)
+                    .map(|(j, _)| (linking[p_idx][j].0, 1.0));
+                model.add_col(cost, 0.0.., [(convexity[p_idx].0, 1.0)].into_iter().chain(rows));
+                column_costs.push(cost);
+                self.stats.n_columns += 1;
+            }
+        }
+
+        (best_bound - 1e-6).ceil().max(0.0) as i32
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+
+use crate::problem::Edge;
+
+/// Longest path distances over the fixed edges, between the nodes involved in
+/// disjunctions. Only reachable pairs are stored, so on rail instances, where
+/// the fixed edges mostly follow each train's route, the size grows with the
+/// number of nodes times the length of a route rather than quadratically.
+///
+/// The fixed edges must not contain a positive cycle.
+pub struct DistanceMatrix {
+    /// For each node of interest, the reachable nodes of interest and their
+    /// distance, sorted by node. Empty for the other nodes.
+    rows: Vec<Vec<(u32, i32)>>,
+}
+
+impl DistanceMatrix {
+    pub fn new(n_nodes: usize, fixed: &[Edge], nodes: impl IntoIterator<Item = u32>) -> Self {
+        let mut outgoing = vec![Vec::new(); n_nodes];
+        for e in fixed.iter() {
+            outgoing[e.src as usize].push((e.tgt, e.weight));
+        }
+        let mut is_key = vec![false; n_nodes];
+        for node in nodes {
+            is_key[node as usize] = true;
+        }
+
+        let mut rows = vec![Vec::new(); n_nodes];
+        let mut dist = vec![i32::MIN; n_nodes];
+        let mut touched = Vec::new();
+        let mut queue = VecDeque::new();
+        for src in (0..n_nodes as u32).filter(|n| is_key[*n as usize]) {
+            dist[src as usize] = 0;
+            touched.push(src);
+            queue.push_back(src);
+            while let Some(node) = queue.pop_front() {
+                let d = dist[node as usize];
+                for (next, weight) in outgoing[node as usize].iter() {
+                    if dist[*next as usize] < d + weight {
+                        if dist[*next as usize] == i32::MIN {
+                            touched.push(*next);
+                        }
+                        dist[*next as usize] = d + weight;
+                        queue.push_back(*next);
+                    }
+                }
+            }
+
+            let row = &mut rows[src as usize];
+            for node in touched.drain(..) {
+                if is_key[node as usize] {
+                    row.push((node, dist[node as usize]));
+                }
+                dist[node as usize] = i32::MIN;
+            }
+            row.sort_unstable();
+        }
+
+        DistanceMatrix { rows }
+    }
+
+    /// The longest path from `src` to `tgt`, if both are nodes of interest
+    /// and there is a path.
+    pub fn get(&self, src: u32, tgt: u32) -> Option<i32> {
+        let row = &self.rows[src as usize];
+        row.binary_search_by_key(&tgt, |(n, _)| *n)
+            .ok()
+            .map(|idx| row[idx].1)
+    }
+
+    /// Whether the fixed edges already imply `edge`.
+    pub fn implies(&self, edge: Edge) -> bool {
+        self.get(edge.src, edge.tgt).is_some_and(|d| d >= edge.weight)
+    }
+
+    /// Whether adding `edge` would close a positive cycle.
+    pub fn closes_positive_cycle(&self, edge: Edge) -> bool {
+        self.get(edge.tgt, edge.src).is_some_and(|d| d + edge.weight > 0)
+    }
+
+    /// Whether choosing both `a` and `b` would close a positive cycle
+    /// through the fixed edges, so that no solution contains both.
+    pub fn excludes(&self, a: Edge, b: Edge) -> bool {
+        match (self.get(a.tgt, b.src), self.get(b.tgt, a.src)) {
+            (Some(d1), Some(d2)) => a.weight + d1 + b.weight + d2 > 0,
+            _ => false,
+        }
+    }
+
+    /// The nodes of interest reachable from `src`, with their distance.
+    pub fn reachable(&self, src: u32) -> &[(u32, i32)] {
+        &self.rows[src as usize]
+    }
+
+    /// Number of stored distances.
+    pub fn n_entries(&self) -> usize {
+        self.rows.iter().map(|r| r.len()).sum()
+    }
+}
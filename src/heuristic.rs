@@ -0,0 +1,190 @@
+use std::{sync::Arc, time::Instant};
+
+use log::info;
+
+use crate::{
+    bnb::{
+        check_limits, go_to, relative_gap, CancelHandle, Limit, Node, Progress, SearchMode,
+        Solution, SolveResult, SolveStatus, SolverSettings, SolverStats,
+    },
+    world::World,
+};
+
+/// Limited discrepancy and beam search. Both explore only part of the tree,
+/// so the lower bound they report is the root bound.
+pub(crate) struct HeuristicSearch<'a> {
+    pub(crate) world: World,
+    pub(crate) world_state: Arc<Node>,
+    pub(crate) node_buf: Vec<Arc<Node>>,
+    pub(crate) settings: &'a SolverSettings,
+    pub(crate) stats: SolverStats,
+    /// Objective, depth and node positions of the best solution.
+    pub(crate) best: Option<(i32, u32, Vec<i32>)>,
+    pub(crate) progress: &'a (dyn Fn(Progress) + Sync),
+    pub(crate) cancel: &'a CancelHandle,
+    pub(crate) deadline: Instant,
+    pub(crate) limit_reached: Option<Limit>,
+}
+
+impl HeuristicSearch<'_> {
+    pub(crate) fn run(mut self, root: &Arc<Node>) -> SolveResult {
+        match self.settings.search_mode {
+            SearchMode::BestFirst => unreachable!(),
+            SearchMode::LimitedDiscrepancy { max_discrepancies } => {
+                for k in 0..=max_discrepancies {
+                    self.limited_discrepancy(root, k);
+                    if self.limit_reached.is_some() || self.ub() <= root.state.lb {
+                        break;
+                    }
+                }
+            }
+            SearchMode::Beam { width } => self.beam(root, width.max(1)),
+        }
+        self.finish(root.state.lb)
+    }
+
+    /// Depth-first search over the paths with at most `k` discrepancies.
+    fn limited_discrepancy(&mut self, root: &Arc<Node>, k: u32) {
+        let mut stack = vec![(root.clone(), 0)];
+        while let Some((node, discrepancies)) = stack.pop() {
+            if self.stopped() {
+                return;
+            }
+            if node.state.lb >= self.ub() {
+                continue;
+            }
+            // Push the best child last, so that it is explored first.
+            let children = self.expand(&node);
+            for (i, child) in children.into_iter().enumerate().rev() {
+                let discrepancies = discrepancies + (i > 0) as u32;
+                if discrepancies <= k {
+                    stack.push((child, discrepancies));
+                }
+            }
+        }
+    }
+
+    fn beam(&mut self, root: &Arc<Node>, width: usize) {
+        let mut level = vec![root.clone()];
+        while !level.is_empty() {
+            let mut next_level = Vec::new();
+            for node in level.iter() {
+                if self.stopped() {
+                    return;
+                }
+                if node.state.lb < self.ub() {
+                    next_level.extend(self.expand(node));
+                }
+            }
+            next_level.sort_by_key(|n| n.state.lb);
+            next_level.truncate(width);
+            level = next_level;
+        }
+    }
+
+    fn ub(&self) -> i32 {
+        self.best.as_ref().map(|b| b.0).unwrap_or(i32::MAX)
+    }
+
+    fn stopped(&mut self) -> bool {
+        if self.limit_reached.is_none() {
+            self.limit_reached = check_limits(
+                self.settings,
+                self.deadline,
+                self.cancel,
+                self.stats.n_nodes_solved,
+                self.world.n_push_edge(),
+                self.world.work_units(),
+            );
+        }
+        self.limit_reached.is_some()
+    }
+
+    /// Branch on `node` and return its children ordered by `lb`. Children
+    /// that are solutions are recorded instead of returned.
+    fn expand(&mut self, node: &Arc<Node>) -> Vec<Arc<Node>> {
+        go_to(&mut self.world, &mut self.world_state, node, &mut self.node_buf);
+        self.stats.n_nodes_solved += 1;
+
+        let mut children = Vec::new();
+        let Some(bs) = node.state.branching.as_ref() else {
+            let positions = self.world.positions();
+            self.new_solution(node.state.lb, node.depth, positions);
+            return children;
+        };
+        for b in bs.iter() {
+            // Alternatives that were not probed can be infeasible.
+            if !self.world.push(*b) {
+                continue;
+            }
+            let state = self.world.mk_state(self.settings, node.state.lb, self.ub());
+            self.stats.n_states_generated += 1;
+            if let Some(state) = state.as_ref() {
+                self.world.record_branching(*b, state.lb - node.state.lb);
+            }
+            match state {
+                Some(state) if state.branching.is_none() => {
+                    let positions = self.world.positions();
+                    self.new_solution(state.lb, node.depth + 1, positions);
+                }
+                Some(state) if !state.branching.as_ref().unwrap().is_empty() => {
+                    self.stats.n_nodes_generated += 1;
+                    self.stats.max_depth = self.stats.max_depth.max(node.depth + 1);
+                    children.push(Arc::new(Node {
+                        id: 0,
+                        state,
+                        depth: node.depth + 1,
+                        parent: Some((node.clone(), *b)),
+                    }));
+                }
+                _ => {}
+            }
+            self.world.pop();
+        }
+        children.sort_by_key(|n| n.state.lb);
+        children
+    }
+
+    fn new_solution(&mut self, objective: i32, depth: u32, positions: Vec<i32>) {
+        if objective < self.ub() {
+            info!("NEW BEST {}", objective);
+            (self.progress)(Progress::NewIncumbent {
+                objective,
+                positions: &positions,
+            });
+            self.best = Some((objective, depth, positions));
+        }
+    }
+
+    fn finish(mut self, root_lb: i32) -> SolveResult {
+        let objective = self.best.as_ref().map(|b| b.0);
+        let lower_bound = root_lb.min(objective.unwrap_or(i32::MAX));
+        let mut stats = self.stats;
+        stats.best_bound = lower_bound;
+        stats.n_push_edge = self.world.n_push_edge();
+        stats.work_units = self.world.work_units();
+        stats.limit_reached = self.limit_reached;
+        stats.solution_depth = self.best.as_ref().map(|b| b.1).unwrap_or(u32::MAX);
+        stats.best_value = objective.unwrap_or(i32::MAX);
+        stats.add_timings(&self.world);
+
+        let status = match (objective, self.limit_reached) {
+            (Some(objective), _) if objective <= lower_bound => SolveStatus::Optimal,
+            (Some(_), _) => SolveStatus::Feasible,
+            (None, Some(limit)) => SolveStatus::from_limit(limit),
+            (None, None) => SolveStatus::NoSolutionFound,
+        };
+
+        SolveResult {
+            status,
+            stats,
+            solution: self.best.take().map(|(objective, _, positions)| Solution {
+                objective,
+                positions,
+            }),
+            lower_bound,
+            gap: relative_gap(objective, lower_bound),
+            trace: None,
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use crate::problem::Edge;
+
+/// Observed bound increases for one alternative.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PseudoCost {
+    pub sum: i64,
+    pub count: u32,
+}
+
+impl PseudoCost {
+    pub fn mean(&self) -> i32 {
+        (self.sum / self.count.max(1) as i64) as i32
+    }
+}
+
+/// Branching statistics learned during the search and kept across restarts.
+#[derive(Default)]
+pub struct BranchingHistory {
+    /// Lower bound increase of the child created by adding an edge, keyed by
+    /// `(src, tgt)`.
+    /// An edge is usually an alternative of a single disjunction, so this is
+    /// the pseudo-cost of that alternative; disjunctions sharing an edge
+    /// share its pseudo-cost, as the edge has the same effect in both.
+    /// Keying by edge lets the children created in `bnb.rs` record their
+    /// bound increase from the branching edge alone.
+    pseudo_costs: HashMap<(u32, u32), PseudoCost>,
+    /// How often each non-unit disjunction was violated by the relaxed schedule.
+    conflicts: Vec<u32>,
+    max_conflicts: u32,
+}
+
+impl BranchingHistory {
+    pub fn new(n_disjunctions: usize) -> Self {
+        BranchingHistory {
+            pseudo_costs: Default::default(),
+            conflicts: vec![0; n_disjunctions],
+            max_conflicts: 0,
+        }
+    }
+
+    pub fn record(&mut self, edge: Edge, lb_increase: i32) {
+        let pc = self.pseudo_costs.entry((edge.src, edge.tgt)).or_default();
+        pc.sum += lb_increase as i64;
+        pc.count += 1;
+    }
+
+    /// The average bound increase of `edge`, if it has been observed at
+    /// least `reliability` times.
+    pub fn estimate(&self, edge: Edge, reliability: u32) -> Option<i32> {
+        self.pseudo_costs
+            .get(&(edge.src, edge.tgt))
+            .filter(|pc| pc.count >= reliability.max(1))
+            .map(|pc| pc.mean())
+    }
+
+    pub fn add_conflict(&mut self, disjunction: usize) {
+        self.conflicts[disjunction] += 1;
+        self.max_conflicts = self.max_conflicts.max(self.conflicts[disjunction]);
+    }
+
+    pub fn conflicts(&self, disjunction: usize) -> u32 {
+        self.conflicts[disjunction]
+    }
+
+    /// Scale a strong-branching `score` of `disjunction` by up to a factor
+    /// of two, relative to the most conflicting disjunction, so that what
+    /// was learned before a restart changes the branching order and does
+    /// not only break ties.
+    pub fn conflict_weighted(&self, disjunction: usize, score: i32) -> i32 {
+        let max = self.max_conflicts.max(1) as i64;
+        let weighted = score as i64 * (max + self.conflicts[disjunction] as i64) / max;
+        weighted.min(i32::MAX as i64) as i32
+    }
+}
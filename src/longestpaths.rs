@@ -1,6 +1,6 @@
-use log::{debug, trace};
+use log::trace;
 
-use crate::problem::{self, Edge};
+use crate::problem::{self, DelayCost, Edge, NodeCost};
 
 pub type Time = i32;
 
@@ -10,6 +10,9 @@ pub struct Node {
     pub delayed_after: Time,
     pub ub: Time,
     pub coeff: i32,
+    pub delay_costs: Vec<DelayCost>,
+    /// Costs in the other objectives, with the objective's index.
+    pub objective_costs: Vec<(u32, NodeCost)>,
 }
 
 pub struct LongestPaths {
@@ -19,7 +22,22 @@ pub struct LongestPaths {
     queue: Vec<u32>,
     trail: Vec<(u32, Time)>,
     pub trail_lim: Vec<u32>,
+    /// The weighted sum of `objective_values`.
     pub objective_value: i32,
+    /// The value of each objective, where objective 0 is the nodes' own
+    /// costs. These never decrease when pushing edges, so they are lower
+    /// bounds for any schedule extending the current one.
+    pub objective_values: Vec<i32>,
+    weights: Vec<i32>,
+    /// Pushing an edge fails if an objective would exceed its limit.
+    limits: Vec<i32>,
+    /// Whether any limit is finite, to skip checking them otherwise.
+    has_limits: bool,
+    /// Number of `push_edge` calls, including hypothetical edges.
+    pub n_push_edge: u64,
+    /// Number of edges scanned while propagating, a machine-independent
+    /// measure of the work done.
+    pub n_edges_scanned: u64,
 }
 
 impl LongestPaths {
@@ -32,6 +50,12 @@ impl LongestPaths {
             trail: Vec::new(),
             trail_lim: Vec::new(),
             objective_value: 0,
+            objective_values: vec![0],
+            weights: vec![1],
+            limits: vec![i32::MAX],
+            has_limits: false,
+            n_push_edge: 0,
+            n_edges_scanned: 0,
         }
     }
 
@@ -43,14 +67,82 @@ impl LongestPaths {
             position: node.lb,
             delayed_after: node.threshold,
             coeff: node.coeff as i32,
+            delay_costs: node.delay_costs.clone(),
+            objective_costs: Vec::new(),
         };
-        self.objective_value += Self::obj_component(&node);
+        let cost = Self::obj_component(&node);
+        self.objective_values[0] += cost;
+        self.objective_value += self.weights[0] * cost;
         self.nodes.push(node);
         self.outgoing.push(Default::default());
     }
 
+    /// Add a cost in objective `objective`, which must be at least 1.
+    pub fn add_objective_cost(&mut self, objective: usize, cost: &NodeCost) {
+        assert!(objective > 0);
+        if self.objective_values.len() <= objective {
+            self.objective_values.resize(objective + 1, 0);
+            self.weights.resize(objective + 1, 0);
+            self.limits.resize(objective + 1, i32::MAX);
+        }
+        let value = cost.cost(self.nodes[cost.node as usize].position);
+        self.objective_values[objective] += value;
+        self.objective_value += self.weights[objective] * value;
+        self.nodes[cost.node as usize]
+            .objective_costs
+            .push((objective as u32, cost.clone()));
+    }
+
+    /// Minimise the sum of the objectives times `weights` instead of
+    /// objective 0, subject to `limits`. Missing weights are 0 and missing
+    /// limits are none. Returns false if the current values already exceed
+    /// a limit.
+    pub fn set_objectives(&mut self, weights: &[u32], limits: &[Option<i32>]) -> bool {
+        let n = self.objective_values.len().max(weights.len()).max(limits.len());
+        self.objective_values.resize(n, 0);
+        self.weights = (0..n).map(|i| weights.get(i).copied().unwrap_or(0) as i32).collect();
+        self.limits = (0..n)
+            .map(|i| limits.get(i).copied().flatten().unwrap_or(i32::MAX))
+            .collect();
+        self.has_limits = self.limits.iter().any(|l| *l < i32::MAX);
+        self.objective_value = self.weighted(&self.objective_values);
+        !self.exceeds_limits()
+    }
+
+    fn weighted(&self, values: &[i32]) -> i32 {
+        values.iter().zip(self.weights.iter()).map(|(v, w)| v * w).sum()
+    }
+
+    fn exceeds_limits(&self) -> bool {
+        self.objective_values.iter().zip(self.limits.iter()).any(|(v, l)| v > l)
+    }
+
+    /// Move `node` to `position`, updating the objectives. Returns the
+    /// change of the weighted objective.
+    fn set_position(&mut self, node: u32, position: Time) -> i32 {
+        let node_data = &mut self.nodes[node as usize];
+        let old_objective = Self::obj_component(node_data);
+        let old_position = node_data.position;
+        node_data.position = position;
+        let delta = Self::obj_component(node_data) - old_objective;
+        self.objective_values[0] += delta;
+        let mut weighted_delta = self.weights[0] * delta;
+        for (objective, cost) in node_data.objective_costs.iter() {
+            let delta = cost.cost(position) - cost.cost(old_position);
+            self.objective_values[*objective as usize] += delta;
+            weighted_delta += self.weights[*objective as usize] * delta;
+        }
+        self.objective_value += weighted_delta;
+        weighted_delta
+    }
+
     pub fn obj_component(node: &Node) -> i32 {
-        node.coeff * node.position.saturating_sub(node.delayed_after)
+        node.coeff * node.position.saturating_sub(node.delayed_after).max(0)
+            + node
+                .delay_costs
+                .iter()
+                .map(|c| c.cost(node.position))
+                .sum::<i32>()
     }
 
     pub fn add_fixed_edge(&mut self, edge: Edge) -> bool {
@@ -74,6 +166,7 @@ impl LongestPaths {
 
         trace!(" push edge {:?}   ++ {:?}", self.edge_undo_stack, edge );
 
+        self.n_push_edge += 1;
         self.outgoing[edge.src as usize].push((edge.tgt, edge.weight));
         self.edge_undo_stack.push(edge);
         self.trail_lim.push(self.trail.len() as u32);
@@ -81,7 +174,9 @@ impl LongestPaths {
         self.queue.push(edge.src);
 
         while let Some(node) = self.queue.pop() {
-            for (next_node, dist) in self.outgoing[node as usize].iter().copied() {
+            self.n_edges_scanned += self.outgoing[node as usize].len() as u64;
+            for out_idx in 0..self.outgoing[node as usize].len() {
+                let (next_node, dist) = self.outgoing[node as usize][out_idx];
                 let target_position = self.nodes[node as usize].position + dist;
                 let next_node_data = &mut self.nodes[next_node as usize];
                 if next_node_data.position < target_position {
@@ -91,15 +186,13 @@ impl LongestPaths {
                     }
 
                     self.trail.push((next_node, next_node_data.position));
-                    let old_objective = Self::obj_component(next_node_data);
-                    let old_position = next_node_data.position;
-                    next_node_data.position = target_position;
-                    let new_objective = Self::obj_component(next_node_data);
-                    
-                    let delta_objective = new_objective - old_objective;
+                    let delta_objective = self.set_position(next_node, target_position);
                     assert!(delta_objective >= 0);
-                    
-                    self.objective_value += delta_objective;
+                    if self.has_limits && self.exceeds_limits() {
+                        self.pop(|_| {});
+                        return false;
+                    }
+
                     if delta_objective > 0 {
                         // debug!("changed position from {} to {}", old_position, target_position);
                         bound_change(next_node, delta_objective);
@@ -132,15 +225,10 @@ impl LongestPaths {
         outgoing.pop();
 
         // Undo assignemnts
-        for (n, p) in self
-            .trail
-            .drain((self.trail_lim.pop().unwrap() as usize)..)
-            .rev()
-        {
-            let node_data = &mut self.nodes[n as usize];
-            self.objective_value -= Self::obj_component(node_data);
-            node_data.position = p;
-            self.objective_value += Self::obj_component(node_data);
+        let lim = self.trail_lim.pop().unwrap() as usize;
+        while self.trail.len() > lim {
+            let (n, p) = self.trail.pop().unwrap();
+            self.set_position(n, p);
             node_changed(n);
         }
     }
@@ -150,11 +238,28 @@ impl LongestPaths {
         self.trail[(start as usize)..].iter().map(|(nd, _)| *nd)
     }
 
-    pub fn hypothetical_edge_lb(&mut self, edge: Edge, bound_change: impl FnMut(u32, i32)) -> bool {
-        self.push_edge(edge, bound_change) && {
+    /// Push `edge` and then the `implied` edges, which every solution
+    /// containing `edge` also satisfies, report the bound changes and pop
+    /// them again. Returns false if any of them is infeasible.
+    pub fn hypothetical_edge_lb(
+        &mut self,
+        edge: Edge,
+        implied: &[Edge],
+        mut bound_change: impl FnMut(u32, i32),
+    ) -> bool {
+        let mut n_pushed = 0;
+        let mut feasible = true;
+        for e in std::iter::once(&edge).chain(implied.iter()) {
+            if !self.push_edge(*e, &mut bound_change) {
+                feasible = false;
+                break;
+            }
+            n_pushed += 1;
+        }
+        for _ in 0..n_pushed {
             self.pop(|_| {});
-            true
         }
+        feasible
     }
 }
 
@@ -1,16 +1,322 @@
 use std::time::Duration;
 
-use bnb::SolverSettings;
+use bnb::{SearchMode, SolverSettings};
 
 pub mod bnb;
+pub mod colgen;
+pub mod distances;
+pub mod heuristic;
+pub mod history;
 pub mod longestpaths;
+pub mod multiobjective;
+pub mod presolve;
 pub mod problem;
+pub mod trace;
+pub mod unionfind;
 pub mod wdg;
 pub mod world;
 
+/// The benchmark's time limit per instance and settings, which is also the
+/// default time limit of a single solve.
+const BENCHMARK_TIMEOUT: Duration = Duration::from_secs(120);
+/// The time limit when the benchmark stops on a node or work limit, which
+/// gives the same results on every machine. It is only a safety net.
+const BENCHMARK_SAFETY_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// A limit for the benchmark from the environment variable `name`, if set.
+fn env_limit<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    match value.parse() {
+        Ok(limit) => Some(limit),
+        Err(_) => {
+            eprintln!("ignoring {}={}, expected a number", name, value);
+            None
+        }
+    }
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(filename: &str) -> T {
+    serde_json::from_str(&std::fs::read_to_string(filename).unwrap()).unwrap()
+}
+
+const USAGE: &str = "usage: disjunctivegraphbounding <instance.json> [<solution.json>] \
+[--initial <file>] [--fix <file>] [--trace <file>] [--max-open-nodes <n>] [--lds <k>] \
+[--beam <width>] [--restart <n>] [--pseudo-costs <r>] [--presolve] [--no-wdg-bound] \
+[--wdg-branching] [--colgen] [--weights <w0,w1,..>] [--lexicographic <i,j,..>] [--pareto <i,j>] \
+[--timeout <seconds>]";
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+/// The value following option `name`.
+fn arg_value<'a>(name: &str, value: Option<&'a String>) -> &'a String {
+    value.unwrap_or_else(|| usage_error(&format!("{} expects a value", name)))
+}
+
+/// The value following option `name`, parsed as a number.
+fn parse_arg<T: std::str::FromStr>(name: &str, value: Option<&String>) -> T {
+    arg_value(name, value)
+        .parse()
+        .unwrap_or_else(|_| usage_error(&format!("{} expects a number", name)))
+}
+
+/// The comma-separated numbers following option `name`.
+fn parse_list<T: std::str::FromStr>(name: &str, value: Option<&String>) -> Vec<T> {
+    arg_value(name, value)
+        .split(',')
+        .map(|n| {
+            n.parse()
+                .unwrap_or_else(|_| usage_error(&format!("{} expects numbers separated by commas", name)))
+        })
+        .collect()
+}
+
+/// Read an assignment, warning if its positions do not match the problem.
+fn read_assignment(filename: &str, problem: &problem::DisjunctiveGraph) -> problem::Assignment {
+    let assignment: problem::Assignment = read_json(filename);
+    if let Some(positions) = assignment.positions.as_ref() {
+        if positions.len() != problem.nodes.len() {
+            eprintln!(
+                "{} has {} positions for {} nodes, edge sets on missing nodes are left open",
+                filename,
+                positions.len(),
+                problem.nodes.len()
+            );
+        }
+    }
+    assignment
+}
+
+/// Solve a single instance and optionally write the solution as JSON.
+///
+/// `--initial <file>` starts from a solution, and `--fix <file>` keeps the
+/// choices of a (partial) assignment fixed. Both files hold `choices` or
+/// `positions`, so a solution written by this program can be used for either.
+/// `--trace <file>` writes the search tree, as DOT if the file name ends in
+/// `.dot` and as JSON otherwise. `--max-open-nodes <n>` bounds memory use,
+/// see `SolverSettings::max_open_nodes`. `--lds <k>` and `--beam <width>`
+/// switch to a heuristic search mode. `--restart <n>` restarts after `n` nodes,
+/// then after twice as many, and so on. `--pseudo-costs <r>` enables
+/// reliability branching with `r` observations per alternative, and
+/// `--presolve` simplifies the instance first. `--no-wdg-bound` leaves out
+/// the WDG bound, which needs every probe, so that pseudo-costs can skip
+/// them. `--wdg-branching` branches as suggested by the WDG solution, and
+/// `--colgen` adds the column generation bound.
+///
+/// With several objectives, see `DisjunctiveGraph::objectives`,
+/// `--weights <w0,w1,..>` minimises their weighted sum and
+/// `--lexicographic <i,j,..>` minimises them in the given order.
+/// `--pareto <i,j>` prints the Pareto front of two objectives and writes
+/// its solutions as a list. These two run several solves, so they cannot
+/// be combined with `--initial` or `--trace`.
+///
+/// `--timeout <seconds>` limits the time, 120 s by default, shared by all
+/// solves.
+fn solve_single(instance: &str, args: &[String]) {
+    let mut problem: problem::DisjunctiveGraph = read_json(instance);
+    let mut solution_file = None;
+    let mut initial_solution: Option<problem::Assignment> = None;
+    let mut trace_file = None;
+    let mut max_open_nodes = None;
+    let mut search_mode = SearchMode::BestFirst;
+    let mut restart_nodes = None;
+    let mut pseudo_cost_reliability = None;
+    let mut presolve = false;
+    let mut use_wdg_bound = true;
+    let mut use_wdg_branching = false;
+    let mut use_colgen_bound = false;
+    let mut objective_weights = Vec::new();
+    let mut lexicographic = None;
+    let mut pareto: Option<[usize; 2]> = None;
+    let mut timeout = BENCHMARK_TIMEOUT;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--initial" => {
+                initial_solution = Some(read_assignment(arg_value(arg, args.next()), &problem))
+            }
+            "--trace" => trace_file = Some(arg_value(arg, args.next())),
+            "--max-open-nodes" => max_open_nodes = Some(parse_arg(arg, args.next())),
+            "--lds" => {
+                let max_discrepancies = parse_arg(arg, args.next());
+                search_mode = SearchMode::LimitedDiscrepancy { max_discrepancies };
+            }
+            "--restart" => restart_nodes = Some(parse_arg(arg, args.next())),
+            "--pseudo-costs" => pseudo_cost_reliability = Some(parse_arg(arg, args.next())),
+            "--presolve" => presolve = true,
+            "--no-wdg-bound" => use_wdg_bound = false,
+            "--wdg-branching" => use_wdg_branching = true,
+            "--colgen" => use_colgen_bound = true,
+            "--weights" => objective_weights = parse_list(arg, args.next()),
+            "--lexicographic" => lexicographic = Some(parse_list(arg, args.next())),
+            "--pareto" => {
+                let objectives = parse_list(arg, args.next()).try_into();
+                pareto = Some(objectives.unwrap_or_else(|_| usage_error("--pareto expects two objectives")));
+            }
+            "--timeout" => timeout = Duration::from_secs(parse_arg(arg, args.next())),
+            "--beam" => {
+                let width = parse_arg(arg, args.next());
+                search_mode = SearchMode::Beam { width };
+            }
+            "--fix" => {
+                let fixed = read_assignment(arg_value(arg, args.next()), &problem);
+                problem = problem.with_fixed(&problem.chosen_edges(&fixed));
+            }
+            _ if arg.starts_with("--") => usage_error(&format!("unknown option {}", arg)),
+            _ => solution_file = Some(arg),
+        }
+    }
+    let mut objectives = lexicographic.iter().flatten().chain(pareto.iter().flatten());
+    if let Some(i) = objectives.find(|i| **i >= problem.n_objectives()) {
+        usage_error(&format!("objective {} does not exist, the instance has {}", i, problem.n_objectives()));
+    }
+    if (lexicographic.is_some() || pareto.is_some())
+        && (initial_solution.is_some() || trace_file.is_some())
+    {
+        usage_error("--lexicographic and --pareto cannot be combined with --initial or --trace");
+    }
+    if use_wdg_branching && !use_wdg_bound {
+        usage_error("--wdg-branching needs the WDG bound");
+    }
+
+    let settings = SolverSettings {
+        use_strong_branching: true,
+        use_wdg_bound,
+        use_relaxed_wdg: false,
+        use_wdg_branching,
+        use_colgen_bound,
+        n_threads: std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1),
+        node_limit: None,
+        push_edge_limit: None,
+        work_limit: None,
+        trace_search: trace_file.is_some(),
+        max_open_nodes,
+        search_mode,
+        restart_nodes,
+        pseudo_cost_reliability,
+        presolve,
+        objective_weights,
+        objective_limits: Vec::new(),
+    };
+
+    // Typing `q` stops the search and keeps the best solution so far.
+    let cancel = bnb::CancelHandle::default();
+    {
+        let cancel = cancel.clone();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lines() {
+                if line.is_ok_and(|l| l.trim() == "q") {
+                    cancel.cancel();
+                }
+            }
+        });
+    }
+
+    if let Some(order) = lexicographic {
+        let result = multiobjective::solve_lexicographic(&problem, &settings, &order, timeout, &cancel);
+        println!("{:?}", result.stats);
+        println!("status {:?}", result.status);
+        match result.solution {
+            Some(solution) => {
+                println!("objectives {:?}", problem.objective_values(&solution.positions));
+                if let Some(filename) = solution_file {
+                    std::fs::write(filename, serde_json::to_string(&solution).unwrap()).unwrap();
+                }
+            }
+            None => println!("no solution"),
+        }
+        return;
+    }
+    if let Some([first, second]) = pareto {
+        let (front, result) =
+            multiobjective::pareto_front(&problem, &settings, first, second, timeout, &cancel);
+        println!("{:?}", result.stats);
+        println!("status of the last solve {:?}", result.status);
+        for point in front.iter() {
+            println!("objectives {:?}", point.values);
+        }
+        if let Some(filename) = solution_file {
+            let solutions = front.iter().map(|p| &p.solution).collect::<Vec<_>>();
+            std::fs::write(filename, serde_json::to_string(&solutions).unwrap()).unwrap();
+        }
+        return;
+    }
+
+    let progress = |p: bnb::Progress| match p {
+        bnb::Progress::NewIncumbent { objective, .. } => println!("incumbent {}", objective),
+        bnb::Progress::BoundImproved { .. } => {}
+        bnb::Progress::Nodes {
+            n_nodes_solved,
+            n_open_nodes,
+            lower_bound,
+            upper_bound,
+            elapsed,
+        } => println!(
+            "{:.0}s nodes {} open {} bounds [{}, {}]",
+            elapsed.as_secs_f64(),
+            n_nodes_solved,
+            n_open_nodes,
+            lower_bound,
+            upper_bound
+        ),
+    };
+    let result = bnb::solve_with_callback(
+        &problem,
+        &settings,
+        timeout,
+        initial_solution.as_ref(),
+        &progress,
+        &cancel,
+    );
+    println!("{:?}", result.stats);
+    if let (Some(trace), Some(filename)) = (result.trace.as_ref(), trace_file) {
+        let contents = if filename.ends_with(".dot") {
+            trace.to_dot()
+        } else {
+            serde_json::to_string(trace).unwrap()
+        };
+        std::fs::write(filename, contents).unwrap();
+    }
+    println!(
+        "status {:?} lower bound {} gap {:.2}%",
+        result.status,
+        result.lower_bound,
+        100.0 * result.gap
+    );
+    match (result.solution, solution_file) {
+        (Some(solution), Some(filename)) => {
+            println!("objective {}", solution.objective);
+            std::fs::write(filename, serde_json::to_string(&solution).unwrap()).unwrap();
+        }
+        (Some(solution), None) => println!("objective {}", solution.objective),
+        (None, _) => println!("no solution"),
+    }
+}
+
 fn main() {
     env_logger::init();
 
+    // With arguments as in `USAGE`, solve one instance. Without arguments,
+    // run the benchmark below, with a node or work limit per solve if
+    // `BENCHMARK_NODE_LIMIT` or `BENCHMARK_WORK_LIMIT` is set.
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let Some((instance, rest)) = args.split_first() {
+        solve_single(instance, rest);
+        return;
+    }
+    let node_limit = env_limit("BENCHMARK_NODE_LIMIT");
+    let work_limit = env_limit("BENCHMARK_WORK_LIMIT");
+    let timeout = if node_limit.is_some() || work_limit.is_some() {
+        BENCHMARK_SAFETY_TIMEOUT
+    } else {
+        BENCHMARK_TIMEOUT
+    };
+
     let mut filenames = std::fs::read_dir("./instances_easy")
         .unwrap()
         .map(|path| path.unwrap().path())
@@ -24,21 +330,77 @@ fn main() {
             use_strong_branching: false,
             use_wdg_bound: false,
             use_relaxed_wdg: false,
+            use_wdg_branching: false,
+            use_colgen_bound: false,
+            n_threads: 1,
+            node_limit,
+            push_edge_limit: None,
+            work_limit,
+            trace_search: false,
+            max_open_nodes: None,
+            search_mode: SearchMode::BestFirst,
+            restart_nodes: None,
+            pseudo_cost_reliability: None,
+            presolve: false,
+            objective_weights: Vec::new(),
+            objective_limits: Vec::new(),
         },
         SolverSettings {
             use_strong_branching: true,
             use_wdg_bound: false,
             use_relaxed_wdg: false,
+            use_wdg_branching: false,
+            use_colgen_bound: false,
+            n_threads: 1,
+            node_limit,
+            push_edge_limit: None,
+            work_limit,
+            trace_search: false,
+            max_open_nodes: None,
+            search_mode: SearchMode::BestFirst,
+            restart_nodes: None,
+            pseudo_cost_reliability: None,
+            presolve: false,
+            objective_weights: Vec::new(),
+            objective_limits: Vec::new(),
         },
         SolverSettings {
             use_strong_branching: true,
             use_wdg_bound: true,
             use_relaxed_wdg: false,
+            use_wdg_branching: false,
+            use_colgen_bound: false,
+            n_threads: 1,
+            node_limit,
+            push_edge_limit: None,
+            work_limit,
+            trace_search: false,
+            max_open_nodes: None,
+            search_mode: SearchMode::BestFirst,
+            restart_nodes: None,
+            pseudo_cost_reliability: None,
+            presolve: false,
+            objective_weights: Vec::new(),
+            objective_limits: Vec::new(),
         },
         SolverSettings {
             use_strong_branching: true,
             use_wdg_bound: true,
             use_relaxed_wdg: true   ,
+            use_wdg_branching: false,
+            use_colgen_bound: false,
+            n_threads: 1,
+            node_limit,
+            push_edge_limit: None,
+            work_limit,
+            trace_search: false,
+            max_open_nodes: None,
+            search_mode: SearchMode::BestFirst,
+            restart_nodes: None,
+            pseudo_cost_reliability: None,
+            presolve: false,
+            objective_weights: Vec::new(),
+            objective_limits: Vec::new(),
         },
     ];
 
@@ -71,9 +433,10 @@ fn main() {
 
             println!("# solving {} {} ...", filename.display(), settings_name);
 
-            let (stats, obj) = bnb::solve(&problem, &settings, Duration::from_secs(120));
-            let objective = obj.map(|x| format!("{}", x)).unwrap_or("-".to_string());
-            println!(" {{ 'name': '{}', 'settings': '{}', 'objective': '{}', 'states': {}, 'nodes_generated': {}, 'nodes_solved': {}, 'max_depth': {}, 'solution_depth': {}, 'root_bound': {}, 'best_bound': {}, 'best_value': {}  }},", 
+            let result = bnb::solve(&problem, &settings, timeout);
+            let stats = &result.stats;
+            let objective = result.solution.as_ref().map(|x| format!("{}", x.objective)).unwrap_or("-".to_string());
+            println!(" {{ 'name': '{}', 'settings': '{}', 'objective': '{}', 'states': {}, 'nodes_generated': {}, 'nodes_solved': {}, 'max_depth': {}, 'solution_depth': {}, 'root_bound': {}, 'best_bound': {}, 'best_value': {}, 'push_edges': {}, 'work_units': {}, 'limit': '{}', 'status': '{:?}', 'gap': {}, 'highs_calls': {}, 'highs_time': {:.3}, 'propagation_time': {:.3}, 'probing_time': {:.3}  }},", 
                 filename.display(), 
                 settings_name, 
                 objective,
@@ -85,6 +448,15 @@ fn main() {
                 stats.root_bound,
                 stats.best_bound,
                 stats.best_value,
+                stats.n_push_edge,
+                stats.work_units,
+                stats.limit_reached.map(|l| format!("{:?}", l)).unwrap_or("-".to_string()),
+                result.status,
+                result.gap,
+                stats.n_highs_calls,
+                stats.time_highs.as_secs_f64(),
+                stats.time_propagation.as_secs_f64(),
+                stats.time_probing.as_secs_f64(),
             );
         }
     }
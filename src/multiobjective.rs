@@ -0,0 +1,115 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    bnb::{self, CancelHandle, Solution, SolveResult, SolveStatus, SolverSettings, SolverStats},
+    problem::DisjunctiveGraph,
+};
+
+/// Minimise the objectives in `order` one after the other, each subject to
+/// the values reached for the ones before, on top of
+/// `settings.objective_limits`. The timeout and `cancel` apply to all the
+/// solves, and the stats are added up.
+///
+/// Returns the last solve's result, whose objective, bound and gap refer to
+/// the last objective in `order`; the values of all objectives are given by
+/// `DisjunctiveGraph::objective_values`. If a solve stops at a limit with a
+/// solution, the following solves are limited by that solution's values
+/// instead, and the result is `Feasible` at best. If a solve finds no
+/// solution, its result is returned.
+pub fn solve_lexicographic(
+    problem: &DisjunctiveGraph,
+    settings: &SolverSettings,
+    order: &[usize],
+    timeout: Duration,
+    cancel: &CancelHandle,
+) -> SolveResult {
+    assert!(!order.is_empty());
+    let deadline = Instant::now() + timeout;
+    let n_objectives = problem.n_objectives();
+    let mut settings = settings.clone();
+    settings.objective_limits.resize(n_objectives, None);
+    let mut stats = SolverStats::new();
+    let mut all_optimal = true;
+    let mut result = None;
+    for &objective in order {
+        settings.objective_weights = (0..n_objectives).map(|i| (i == objective) as u32).collect();
+        let mut solved = bnb::solve_with_callback(
+            problem,
+            &settings,
+            deadline.saturating_duration_since(Instant::now()),
+            None,
+            &|_| {},
+            cancel,
+        );
+        stats.merge(&solved.stats);
+        let Some(solution) = solved.solution.as_ref() else {
+            solved.stats = stats;
+            return solved;
+        };
+        let value = problem.objective_values(&solution.positions)[objective];
+        let limit = &mut settings.objective_limits[objective];
+        *limit = Some(limit.map_or(value, |l| l.min(value)));
+        all_optimal &= solved.status == SolveStatus::Optimal;
+        result = Some(solved);
+    }
+
+    let mut result = result.unwrap();
+    result.stats = stats;
+    if !all_optimal {
+        result.status = SolveStatus::Feasible;
+    }
+    result
+}
+
+/// A solution on the Pareto front, with the value of each objective.
+pub struct ParetoPoint {
+    pub values: Vec<i32>,
+    pub solution: Solution,
+}
+
+/// The Pareto front of objectives `first` and `second` by the ε-constraint
+/// method: minimise `first` and then `second` with `solve_lexicographic`,
+/// and repeat with `second` limited to one less than in the last point,
+/// until that is infeasible. The points are ordered by increasing `first`
+/// and decreasing `second`.
+///
+/// The search stops after the first solve that is not optimal, keeping its
+/// solution as the last point, so the front is only complete if the last
+/// result's status is `Optimal` or `Infeasible`. Returns the points and the
+/// last result, whose stats are added up over all the solves.
+pub fn pareto_front(
+    problem: &DisjunctiveGraph,
+    settings: &SolverSettings,
+    first: usize,
+    second: usize,
+    timeout: Duration,
+    cancel: &CancelHandle,
+) -> (Vec<ParetoPoint>, SolveResult) {
+    assert!(first != second);
+    let deadline = Instant::now() + timeout;
+    let mut settings = settings.clone();
+    settings.objective_limits.resize(problem.n_objectives(), None);
+    let mut stats = SolverStats::new();
+    let mut front = Vec::new();
+    loop {
+        let mut result = solve_lexicographic(
+            problem,
+            &settings,
+            &[first, second],
+            deadline.saturating_duration_since(Instant::now()),
+            cancel,
+        );
+        stats.merge(&result.stats);
+        let Some(solution) = result.solution.take() else {
+            result.stats = stats;
+            return (front, result);
+        };
+        let values = problem.objective_values(&solution.positions);
+        settings.objective_limits[second] = Some(values[second] - 1);
+        front.push(ParetoPoint { values, solution });
+        if result.status != SolveStatus::Optimal {
+            result.stats = stats;
+            return (front, result);
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{HashMap, VecDeque};
+
+use log::info;
+use tinyvec::TinyVec;
+
+use crate::{
+    distances::DistanceMatrix,
+    longestpaths::LongestPaths,
+    problem::{DisjunctiveGraph, Edge},
+};
+
+/// What `presolve` removed or changed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PresolveStats {
+    /// Disjunctions with an alternative that always holds, given the fixed
+    /// edges and the node bounds.
+    pub n_implied_disjunctions: usize,
+    /// Alternatives that would create a positive cycle or exceed a node's `ub`.
+    pub n_infeasible_alternatives: usize,
+    /// Disjunctions with a single feasible alternative left, which is fixed.
+    pub n_fixed_disjunctions: usize,
+    /// Alternatives with the same nodes as a weaker alternative in the same set.
+    pub n_redundant_alternatives: usize,
+    /// Edge sets equal to an earlier edge set.
+    pub n_duplicate_edge_sets: usize,
+    /// Edge sets that hold whenever another edge set holds.
+    pub n_dominated_edge_sets: usize,
+    pub n_lb_tightened: usize,
+    pub n_ub_tightened: usize,
+}
+
+/// Simplify the problem before the search. Returns `None` if the problem is
+/// found to be infeasible.
+///
+/// The result has the same nodes, so node positions remain valid for both
+/// problems, but edge sets may be removed or reduced. Removed edge sets are
+/// implied by the remaining ones, so every solution of the result is a
+/// solution of `problem` with the same objective value.
+pub fn presolve(problem: &DisjunctiveGraph) -> Option<(DisjunctiveGraph, PresolveStats)> {
+    let mut stats = PresolveStats::default();
+    let n_nodes = problem.nodes.len();
+
+    let mut edge_sets = problem
+        .edge_sets
+        .iter()
+        .map(|es| Some(weakest_alternatives(es)))
+        .collect::<Vec<_>>();
+    stats.n_redundant_alternatives = problem
+        .edge_sets
+        .iter()
+        .zip(edge_sets.iter())
+        .map(|(a, b)| a.len() - b.as_ref().unwrap().len())
+        .sum();
+
+    // Fixing an alternative tightens the bounds and distances, which may
+    // decide more disjunctions, so repeat until nothing changes. Bounds and
+    // distances only get tighter, so within a round they stay valid.
+    let (lb, ub) = loop {
+        let fixed = edge_sets
+            .iter()
+            .flatten()
+            .filter(|es| es.len() == 1)
+            .map(|es| es[0])
+            .collect::<Vec<_>>();
+        let (lb, ub) = node_bounds(problem, &fixed)?;
+        let distances = DistanceMatrix::new(
+            n_nodes,
+            &fixed,
+            edge_sets
+                .iter()
+                .flatten()
+                .filter(|es| es.len() >= 2)
+                .flat_map(|es| es.iter().flat_map(|e| [e.src, e.tgt])),
+        );
+
+        let mut changed = false;
+        for slot in edge_sets.iter_mut() {
+            let Some(es) = slot.as_mut().filter(|es| es.len() >= 2) else {
+                continue;
+            };
+
+            let implied = es.iter().any(|e| {
+                (ub[e.src as usize] as i64) + (e.weight as i64) <= lb[e.tgt as usize] as i64
+                    || distances.implies(*e)
+            });
+            if implied {
+                stats.n_implied_disjunctions += 1;
+                *slot = None;
+                continue;
+            }
+
+            let n_before = es.len();
+            es.retain(|e| {
+                (lb[e.src as usize] as i64) + (e.weight as i64) <= ub[e.tgt as usize] as i64
+                    && !distances.closes_positive_cycle(*e)
+            });
+            stats.n_infeasible_alternatives += n_before - es.len();
+            match es.len() {
+                0 => return None,
+                1 => {
+                    stats.n_fixed_disjunctions += 1;
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+
+        if !changed {
+            break (lb, ub);
+        }
+    };
+
+    remove_dominated_edge_sets(&mut edge_sets, &mut stats);
+
+    let mut result = problem.clone();
+    result.edge_sets = edge_sets.into_iter().flatten().collect();
+    for (idx, node) in result.nodes.iter_mut().enumerate() {
+        if lb[idx] > node.lb {
+            node.lb = lb[idx];
+            stats.n_lb_tightened += 1;
+        }
+        if ub[idx] < node.ub {
+            node.ub = ub[idx];
+            stats.n_ub_tightened += 1;
+        }
+    }
+
+    info!(
+        "presolve: {} of {} edge sets left, {:?}",
+        result.edge_sets.len(),
+        problem.edge_sets.len(),
+        stats
+    );
+    Some((result, stats))
+}
+
+/// Drop alternatives that have the same nodes as one with a smaller weight,
+/// since the disjunction holds whenever they hold.
+fn weakest_alternatives(es: &TinyVec<[Edge; 2]>) -> TinyVec<[Edge; 2]> {
+    let mut result: TinyVec<[Edge; 2]> = Default::default();
+    for e in es.iter() {
+        match result.iter_mut().find(|x| x.src == e.src && x.tgt == e.tgt) {
+            Some(x) => x.weight = x.weight.min(e.weight),
+            None => result.push(*e),
+        }
+    }
+    result
+}
+
+/// The earliest and latest time of each node given the fixed edges, or
+/// `None` if the fixed edges are infeasible.
+fn node_bounds(problem: &DisjunctiveGraph, fixed: &[Edge]) -> Option<(Vec<i32>, Vec<i32>)> {
+    let mut schedule = LongestPaths::new();
+    for node in problem.nodes.iter() {
+        schedule.add_node(node);
+    }
+    for e in fixed.iter() {
+        if !schedule.add_fixed_edge(*e) {
+            return None;
+        }
+    }
+    let lb = schedule.nodes.iter().map(|n| n.position).collect::<Vec<_>>();
+
+    // Propagate the upper bounds backwards. The fixed edges have no positive
+    // cycles, so this terminates.
+    let mut incoming = vec![Vec::new(); problem.nodes.len()];
+    for e in fixed.iter() {
+        incoming[e.tgt as usize].push(*e);
+    }
+    let mut ub = problem.nodes.iter().map(|n| n.ub).collect::<Vec<_>>();
+    let mut queue = (0..problem.nodes.len() as u32).collect::<VecDeque<_>>();
+    while let Some(node) = queue.pop_front() {
+        if ub[node as usize] == i32::MAX {
+            continue;
+        }
+        for e in incoming[node as usize].iter() {
+            let latest = ub[node as usize].saturating_sub(e.weight);
+            if latest < ub[e.src as usize] {
+                ub[e.src as usize] = latest;
+                queue.push_back(e.src);
+            }
+        }
+    }
+
+    lb.iter().zip(ub.iter()).all(|(l, u)| l <= u).then_some((lb, ub))
+}
+
+/// Remove edge sets where every alternative of another edge set implies one
+/// of their alternatives. Of two equal edge sets, the first is kept.
+fn remove_dominated_edge_sets(
+    edge_sets: &mut [Option<TinyVec<[Edge; 2]>>],
+    stats: &mut PresolveStats,
+) {
+    // Implies `b`: same nodes and at least the same weight.
+    let covers = |a: &[Edge], b: &[Edge]| {
+        a.iter().all(|x| {
+            b.iter()
+                .any(|y| x.src == y.src && x.tgt == y.tgt && x.weight >= y.weight)
+        })
+    };
+
+    let mut by_nodes: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+    for (idx, es) in edge_sets.iter().enumerate() {
+        for e in es.iter().flatten() {
+            by_nodes.entry((e.src, e.tgt)).or_default().push(idx);
+        }
+    }
+
+    // Following the removals from any edge set ends at one that is kept, so
+    // removed edge sets can still be used to remove others.
+    let original = edge_sets.to_vec();
+    for (b_idx, b) in original.iter().enumerate() {
+        let Some(b) = b.as_ref() else {
+            continue;
+        };
+        let mut dominated = None;
+        for e in b.iter() {
+            for &a_idx in by_nodes[&(e.src, e.tgt)].iter() {
+                if a_idx == b_idx {
+                    continue;
+                }
+                if let Some(a) = original[a_idx].as_ref().filter(|a| covers(a, b)) {
+                    let equal = covers(b, a);
+                    if !equal || a_idx < b_idx {
+                        dominated = Some(equal);
+                        break;
+                    }
+                }
+            }
+            if dominated.is_some() {
+                break;
+            }
+        }
+        match dominated {
+            Some(true) => stats.n_duplicate_edge_sets += 1,
+            Some(false) => stats.n_dominated_edge_sets += 1,
+            None => continue,
+        }
+        edge_sets[b_idx] = None;
+    }
+}
@@ -1,7 +1,7 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tinyvec::TinyVec;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[derive(Default ,Clone, Copy)]
 #[derive(Debug)]
 pub struct Edge {
@@ -10,16 +10,148 @@ pub struct Edge {
     pub weight :i32,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct Node {
     pub lb :i32,
     pub ub :i32,
     pub coeff :u32,
     pub threshold :i32,
+    /// Added to `coeff * max(0, position - threshold)`.
+    #[serde(default)]
+    pub delay_costs: Vec<DelayCost>,
 }
 
-#[derive(Deserialize)]
+/// A delay cost of `slope` per time unit after `threshold`, plus `step` once
+/// the position is after `threshold`. A node with several of them has a
+/// non-decreasing piecewise-linear cost, such as a penalty for being more
+/// than 3 minutes late and a larger one for more than 5 minutes.
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub struct DelayCost {
+    pub threshold: i32,
+    #[serde(default)]
+    pub slope: u32,
+    #[serde(default)]
+    pub step: u32,
+}
+
+impl DelayCost {
+    pub fn cost(&self, position: i32) -> i32 {
+        if position > self.threshold {
+            self.slope as i32 * (position - self.threshold) + self.step as i32
+        } else {
+            0
+        }
+    }
+}
+
+impl Node {
+    pub fn cost(&self, position: i32) -> i32 {
+        self.coeff as i32 * position.saturating_sub(self.threshold).max(0)
+            + self.delay_costs.iter().map(|c| c.cost(position)).sum::<i32>()
+    }
+}
+
+/// A cost on the position of `node`, of the same form as a `Node`'s cost.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct NodeCost {
+    pub node: u32,
+    #[serde(default)]
+    pub coeff: u32,
+    #[serde(default)]
+    pub threshold: i32,
+    #[serde(default)]
+    pub delay_costs: Vec<DelayCost>,
+}
+
+impl NodeCost {
+    pub fn cost(&self, position: i32) -> i32 {
+        self.coeff as i32 * position.saturating_sub(self.threshold).max(0)
+            + self.delay_costs.iter().map(|c| c.cost(position)).sum::<i32>()
+    }
+}
+
+/// An objective besides the nodes' own costs, such as freight train delay
+/// when the nodes' costs are passenger delay.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Objective {
+    #[serde(default)]
+    pub name: String,
+    pub costs: Vec<NodeCost>,
+}
+
+#[derive(Deserialize, Clone)]
 pub struct DisjunctiveGraph {
     pub nodes :Vec<Node>,
     pub edge_sets: Vec<TinyVec<[Edge; 2]>>,
+    /// Further objectives. The nodes' own costs are objective 0, and
+    /// `objectives[i]` is objective `i + 1`. Which of them are minimised is
+    /// chosen by `SolverSettings::objective_weights`.
+    #[serde(default)]
+    pub objectives: Vec<Objective>,
+}
+
+/// A choice of alternatives for some or all of the edge sets, either as the
+/// index of the chosen edge per edge set, or as node positions (such as a
+/// solution written by the solver) to read the choices from.
+#[derive(Deserialize, Default)]
+pub struct Assignment {
+    #[serde(default)]
+    pub choices: Option<Vec<Option<usize>>>,
+    #[serde(default)]
+    pub positions: Option<Vec<i32>>,
+}
+
+impl DisjunctiveGraph {
+    /// The edges chosen by `assignment`, with the index of their edge set.
+    /// From positions, the first alternative that the positions satisfy is
+    /// chosen, and edge sets with none satisfied, or with nodes beyond the
+    /// end of the positions, are left open.
+    pub fn chosen_edges(&self, assignment: &Assignment) -> Vec<(usize, Edge)> {
+        let mut chosen = Vec::new();
+        for (set_idx, edges) in self.edge_sets.iter().enumerate() {
+            if edges.len() < 2 {
+                continue;
+            }
+            let choice = if let Some(choices) = assignment.choices.as_ref() {
+                choices.get(set_idx).copied().flatten()
+            } else if let Some(positions) = assignment.positions.as_ref() {
+                edges.iter().position(|e| {
+                    match (positions.get(e.src as usize), positions.get(e.tgt as usize)) {
+                        (Some(src), Some(tgt)) => src + e.weight <= *tgt,
+                        _ => false,
+                    }
+                })
+            } else {
+                None
+            };
+            if let Some(e) = choice.and_then(|c| edges.get(c)) {
+                chosen.push((set_idx, *e));
+            }
+        }
+        chosen
+    }
+
+    pub fn n_objectives(&self) -> usize {
+        1 + self.objectives.len()
+    }
+
+    /// The value of each objective for the given node positions.
+    pub fn objective_values(&self, positions: &[i32]) -> Vec<i32> {
+        let own = self.nodes.iter().zip(positions.iter()).map(|(n, p)| n.cost(*p)).sum();
+        std::iter::once(own)
+            .chain(self.objectives.iter().map(|o| {
+                o.costs.iter().map(|c| c.cost(positions[c.node as usize])).sum()
+            }))
+            .collect()
+    }
+
+    /// A copy of the problem where the chosen edges are fixed, e.g. to keep
+    /// some train orders from the current plan.
+    pub fn with_fixed(&self, chosen: &[(usize, Edge)]) -> DisjunctiveGraph {
+        let mut problem = self.clone();
+        for (set_idx, edge) in chosen.iter() {
+            problem.edge_sets[*set_idx] = [*edge].into_iter().collect();
+        }
+        problem
+    }
 }
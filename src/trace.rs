@@ -0,0 +1,113 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::problem::Edge;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum NodeStatus {
+    /// Taken from the queue and branched on.
+    Solved,
+    /// Cut off because its lower bound reached the incumbent's value.
+    Pruned,
+    /// A disjunction has no feasible alternative left.
+    Infeasible,
+    /// All disjunctions are satisfied.
+    Solution,
+    /// Still open when a limit stopped the search.
+    Open,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TraceNode {
+    pub id: usize,
+    pub parent: Option<usize>,
+    pub depth: u32,
+    /// `None` when the node was pruned before its bound was computed.
+    pub lb: Option<i32>,
+    /// The edge added to the parent's state.
+    pub edge: Option<Edge>,
+    pub status: NodeStatus,
+    /// Seconds since the start of the search.
+    pub processed_at: Option<f64>,
+}
+
+/// One worker's part of the search trace.
+#[derive(Default)]
+pub struct TraceRecorder {
+    pub nodes: Vec<TraceNode>,
+    pub processed: Vec<(usize, f64)>,
+}
+
+/// The branch-and-bound tree, as written by `--trace`.
+#[derive(Debug, Default, Serialize)]
+pub struct SearchTrace {
+    pub nodes: Vec<TraceNode>,
+}
+
+impl SearchTrace {
+    /// Combine the workers' records. Nodes that were never processed are
+    /// pruned if their bound reaches the final upper bound `ub`.
+    pub fn merge(recorders: Vec<TraceRecorder>, ub: i32) -> SearchTrace {
+        let mut nodes = Vec::new();
+        let mut processed = Vec::new();
+        for r in recorders {
+            nodes.extend(r.nodes);
+            processed.extend(r.processed);
+        }
+        nodes.sort_by_key(|n| n.id);
+        for (id, time) in processed {
+            if let Ok(idx) = nodes.binary_search_by_key(&id, |n| n.id) {
+                let node = &mut nodes[idx];
+                node.processed_at = Some(time);
+                if node.status == NodeStatus::Open {
+                    node.status = NodeStatus::Solved;
+                }
+            }
+        }
+        for node in nodes.iter_mut() {
+            if node.status == NodeStatus::Open && node.lb.is_some_and(|lb| lb >= ub) {
+                node.status = NodeStatus::Pruned;
+            }
+        }
+        SearchTrace { nodes }
+    }
+
+    /// Graphviz DOT, with nodes labelled by their bound and edges by the
+    /// branching edge.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph search {{").unwrap();
+        writeln!(dot, "  node [shape=box, style=filled];").unwrap();
+        for n in self.nodes.iter() {
+            let color = match n.status {
+                NodeStatus::Solved => "white",
+                NodeStatus::Pruned => "lightgrey",
+                NodeStatus::Infeasible => "salmon",
+                NodeStatus::Solution => "palegreen",
+                NodeStatus::Open => "lightblue",
+            };
+            let lb = n.lb.map(|lb| lb.to_string()).unwrap_or("-".to_string());
+            let time = n
+                .processed_at
+                .map(|t| format!("\\nt={:.3}s", t))
+                .unwrap_or_default();
+            writeln!(
+                dot,
+                "  n{} [label=\"#{} lb={}{}\", fillcolor={}];",
+                n.id, n.id, lb, time, color
+            )
+            .unwrap();
+            if let (Some(parent), Some(e)) = (n.parent, n.edge) {
+                writeln!(
+                    dot,
+                    "  n{} -> n{} [label=\"{}->{} ({})\"];",
+                    parent, n.id, e.src, e.tgt, e.weight
+                )
+                .unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
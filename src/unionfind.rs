@@ -0,0 +1,52 @@
+/// Union-find that can undo its unions in reverse order, to follow the
+/// edges pushed and popped during the search. Uses union by size without
+/// path compression, so `find` takes logarithmic time.
+pub struct TrailUnionFind {
+    parent: Vec<u32>,
+    size: Vec<u32>,
+    /// One entry per `union`: the root that was attached to another root,
+    /// or `None` if the elements were already in the same set.
+    trail: Vec<Option<u32>>,
+}
+
+impl TrailUnionFind {
+    pub fn new(n: usize) -> Self {
+        TrailUnionFind {
+            parent: (0..n as u32).collect(),
+            size: vec![1; n],
+            trail: Vec::new(),
+        }
+    }
+
+    pub fn find(&self, mut x: u32) -> u32 {
+        while self.parent[x as usize] != x {
+            x = self.parent[x as usize];
+        }
+        x
+    }
+
+    pub fn union(&mut self, a: u32, b: u32) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            self.trail.push(None);
+            return;
+        }
+        let (small, large) = if self.size[a as usize] < self.size[b as usize] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        self.parent[small as usize] = large;
+        self.size[large as usize] += self.size[small as usize];
+        self.trail.push(Some(small));
+    }
+
+    /// Undo the last `union`.
+    pub fn undo(&mut self) {
+        if let Some(small) = self.trail.pop().unwrap() {
+            let large = self.parent[small as usize];
+            self.size[large as usize] -= self.size[small as usize];
+            self.parent[small as usize] = small;
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use log::{debug, trace};
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
 use tinyvec::TinyVec;
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -10,17 +13,63 @@ pub struct WdgEdge {
 
 type WdgEdgeSet = TinyVec<[WdgEdge; 8]>;
 
+/// Counters accumulated over all solves, not reset by `clear`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WdgStats {
+    pub n_highs_calls: usize,
+    pub n_dominated_disjunctions: usize,
+    /// Cuts added to the relaxation, see `staircase_cuts`.
+    pub n_cuts: usize,
+    /// Time spent adding disjunctions and building the HiGHS model.
+    pub build_time: Duration,
+    pub highs_time: Duration,
+}
+
+/// The result of `WdgSolverBinaryMIP::solve`.
+#[derive(Debug, Default)]
+pub struct WdgSolution {
+    pub value: i32,
+    /// For each disjunction in the model, its id as given to
+    /// `add_disjunction` and the value of its variable: 1 when the first
+    /// alternative is chosen, 0 for the second, and possibly in between when
+    /// solving the relaxation. Dominated disjunctions are not included.
+    pub choices: Vec<(usize, f64)>,
+}
+
 #[derive(Default)]
 pub struct WdgSolverBinaryMIP {
     disjunctions: Vec<(WdgEdgeSet, WdgEdgeSet)>,
+    /// The caller's id of each disjunction, and whether its alternatives
+    /// were swapped.
+    disjunction_ids: Vec<(usize, bool)>,
     simple_pair_disjunctions: HashMap<(u32, u32), Vec<usize>>,
+    /// The disjunctions that were kept, by each partition they affect.
+    partition_disjunctions: HashMap<u32, Vec<usize>>,
     dominated_disjunctions: HashSet<usize>,
+    pub stats: WdgStats,
 }
 
 fn label_dominates(ds: &[(WdgEdgeSet, WdgEdgeSet)], a: usize, b: usize) -> bool {
     ds[a].0[0].d_cost >= ds[b].0[0].d_cost && ds[a].1[0].d_cost >= ds[b].1[0].d_cost
 }
 
+/// Whether choosing `a` implies a cost of at least `b` on every partition in `b`.
+fn alternative_covers(a: &[WdgEdge], b: &[WdgEdge]) -> bool {
+    b.iter().all(|y| {
+        a.iter()
+            .any(|x| x.partition == y.partition && x.d_cost >= y.d_cost)
+    })
+}
+
+/// Whether either alternative of `a` implies one of the alternatives of `b`,
+/// which makes `b` redundant. Generalises `label_dominates` to alternatives
+/// affecting several partitions.
+fn dominates(ds: &[(WdgEdgeSet, WdgEdgeSet)], a: usize, b: usize) -> bool {
+    let ((a1, a2), (b1, b2)) = (&ds[a], &ds[b]);
+    (alternative_covers(a1, b1) || alternative_covers(a1, b2))
+        && (alternative_covers(a2, b1) || alternative_covers(a2, b2))
+}
+
 fn remove_dominated(
     ds: &[(WdgEdgeSet, WdgEdgeSet)],
     front: &mut Vec<usize>,
@@ -52,23 +101,121 @@ fn remove_dominated(
     true
 }
 
+/// Cuts `alpha * c1 + beta * c2 >= rhs` on the costs of partitions `p1` and
+/// `p2`, from the simple pairs between them in `front`.
+///
+/// Choosing the first alternative for the pairs with the `k` smallest costs
+/// on `p1` and the second for the others gives a staircase of integer
+/// solutions in `(c1, c2)`. The relaxation can mix the alternatives of
+/// different pairs and go below the staircase, so we add the edges of its
+/// lower convex hull, as for clique cuts.
+fn staircase_cuts(ds: &[(WdgEdgeSet, WdgEdgeSet)], front: &[usize]) -> Vec<(i64, i64, i64)> {
+    let mut costs = front
+        .iter()
+        .map(|d| (ds[*d].0[0].d_cost as i64, ds[*d].1[0].d_cost as i64))
+        .collect::<Vec<_>>();
+    costs.sort_unstable();
+
+    // The steps: pay the `k`-th cost on `p1` and the largest remaining on `p2`.
+    let mut steps = Vec::new();
+    let mut c2 = 0;
+    for k in (0..=costs.len()).rev() {
+        let c1 = if k == 0 { 0 } else { costs[k - 1].0 };
+        steps.push((c1, c2));
+        if k > 0 {
+            c2 = c2.max(costs[k - 1].1);
+        }
+    }
+    steps.reverse();
+
+    // The lower convex hull, with increasing `c1` and decreasing `c2`.
+    let mut hull: Vec<(i64, i64)> = Vec::new();
+    for (u, v) in steps {
+        if let Some(last) = hull.last_mut() {
+            if v >= last.1 {
+                continue;
+            }
+            if u == last.0 {
+                *last = (u, v);
+                continue;
+            }
+        }
+        while let [.., (u0, v0), (u1, v1)] = hull[..] {
+            if (v1 - v0) * (u - u1) >= (v - v1) * (u1 - u0) {
+                hull.pop();
+            } else {
+                break;
+            }
+        }
+        hull.push((u, v));
+    }
+
+    hull.windows(2)
+        .map(|w| {
+            let [(u1, v1), (u2, v2)] = [w[0], w[1]];
+            let (alpha, beta) = (v1 - v2, u2 - u1);
+            (alpha, beta, alpha * u1 + beta * v1)
+        })
+        .collect()
+}
+
 impl WdgSolverBinaryMIP {
     pub fn clear(&mut self) {
         self.disjunctions.clear();
+        self.disjunction_ids.clear();
         self.simple_pair_disjunctions.clear();
+        self.partition_disjunctions.clear();
         self.dominated_disjunctions.clear();
     }
 
-    pub fn add_disjunction(&mut self, alt1: &TinyVec<[WdgEdge; 8]>, alt2: &TinyVec<[WdgEdge; 8]>) {
-        // Is it a simple pair?
+    /// Add a disjunction, identified by `id` in `WdgSolution::choices`.
+    pub fn add_disjunction(
+        &mut self,
+        id: usize,
+        alt1: &TinyVec<[WdgEdge; 8]>,
+        alt2: &TinyVec<[WdgEdge; 8]>,
+    ) {
+        let start = Instant::now();
         let simple_pair = alt1.len() == 1 && alt2.len() == 1;
         if simple_pair && alt1[0].partition > alt2[0].partition {
-            self.add_disjunction(alt2, alt1);
-            return;
+            // Swap the alternatives so that the simple pairs are keyed by
+            // `(p1, p2)` with `p1 < p2`.
+            self.add_disjunction_inner(id, alt2, alt1, true);
+        } else {
+            self.add_disjunction_inner(id, alt1, alt2, false);
         }
+        self.stats.build_time += start.elapsed();
+    }
+
+    fn add_disjunction_inner(
+        &mut self,
+        id: usize,
+        alt1: &TinyVec<[WdgEdge; 8]>,
+        alt2: &TinyVec<[WdgEdge; 8]>,
+        swapped: bool,
+    ) {
+        // Is it a simple pair?
+        let simple_pair = alt1.len() == 1 && alt2.len() == 1;
 
         let new_elem = self.disjunctions.len();
         self.disjunctions.push((alt1.clone(), alt2.clone()));
+        self.disjunction_ids.push((id, swapped));
+
+        // An alternative of a dominating disjunction covers `alt1` or `alt2`
+        // (either one alternative covers `alt1`, or both cover `alt2`), so it
+        // affects the first partition of `alt1` or of `alt2`. Disjunctions
+        // dominated by others are still checked, since whatever dominates
+        // them is kept and dominates the new one too.
+        let dominated = [alt1[0].partition, alt2[0].partition].iter().any(|p| {
+            self.partition_disjunctions
+                .get(p)
+                .is_some_and(|ds| ds.iter().any(|d| dominates(&self.disjunctions, *d, new_elem)))
+        });
+        if dominated {
+            self.pop_disjunction();
+            self.stats.n_dominated_disjunctions += 1;
+            return;
+        }
 
         if simple_pair {
             // if alt1[0].partition == alt2[0].partition {
@@ -78,26 +225,64 @@ impl WdgSolverBinaryMIP {
 
             let disjunction_ref_list = self.simple_pair_disjunctions.entry((p1, p2)).or_default();
 
+            let n_dominated = &mut self.stats.n_dominated_disjunctions;
             let dom = |d| {
                 // println!("add.dominated: {:?}", self.disjunctions[d]);
                 self.dominated_disjunctions.insert(d);
+                *n_dominated += 1;
             };
             if remove_dominated(&self.disjunctions, disjunction_ref_list, new_elem, dom) {
                 disjunction_ref_list.push(new_elem);
             } else {
                 // Undo the insertion -- this new disjunction was dominated.
                 // println!("ins.dominated: {:?}", self.disjunctions[new_elem]);
-                self.disjunctions.pop();
+                self.pop_disjunction();
+                self.stats.n_dominated_disjunctions += 1;
                 return;
             }
+        } else {
+            // Remove the disjunctions that the new one dominates. Their first
+            // alternative is covered, so it affects one of our partitions.
+            let mut candidates = alt1
+                .iter()
+                .chain(alt2.iter())
+                .flat_map(|e| self.partition_disjunctions.get(&e.partition))
+                .flatten()
+                .copied()
+                .filter(|d| !self.dominated_disjunctions.contains(d))
+                .collect::<Vec<_>>();
+            candidates.sort_unstable();
+            candidates.dedup();
+            for d in candidates {
+                if dominates(&self.disjunctions, new_elem, d) {
+                    self.dominated_disjunctions.insert(d);
+                    self.stats.n_dominated_disjunctions += 1;
+                }
+            }
+        }
+
+        let mut partitions = alt1
+            .iter()
+            .chain(alt2.iter())
+            .map(|e| e.partition)
+            .collect::<Vec<_>>();
+        partitions.sort_unstable();
+        partitions.dedup();
+        for p in partitions {
+            self.partition_disjunctions.entry(p).or_default().push(new_elem);
         }
 
         // println!("not dominated: {:?}", self.disjunctions[new_elem]);
     }
 
-    pub fn solve(&mut self, n_partitions: usize, relaxed: bool) -> i32 {
+    fn pop_disjunction(&mut self) {
+        self.disjunctions.pop();
+        self.disjunction_ids.pop();
+    }
+
+    pub fn solve(&mut self, n_partitions: usize, relaxed: bool) -> WdgSolution {
         if self.disjunctions.is_empty() {
-            return 0;
+            return WdgSolution::default();
         }
 
         debug!("wdg_solve:");
@@ -105,6 +290,7 @@ impl WdgSolverBinaryMIP {
             debug!("  -  {:?}", x);
         }
 
+        let build_start = Instant::now();
         let mut problem = highs::RowProblem::new();
 
         // Cost Vars
@@ -122,6 +308,7 @@ impl WdgSolverBinaryMIP {
             )
         });
 
+        let mut vars = Vec::new();
         for d_idx in sorted {
             //for (d_idx, (alt1, alt2)) in self.disjunctions.iter().enumerate() {
             let (alt1, alt2) = &self.disjunctions[d_idx];
@@ -135,6 +322,7 @@ impl WdgSolverBinaryMIP {
             } else {
                 problem.add_integer_column(0.0, 0..1)
             };
+            vars.push(d_idx);
 
             // alt1 constraints
             for WdgEdge { partition, d_cost } in alt1.iter() {
@@ -159,9 +347,34 @@ impl WdgSolverBinaryMIP {
             }
         }
 
+        if relaxed {
+            let mut pairs = self.simple_pair_disjunctions.iter().collect::<Vec<_>>();
+            pairs.sort_unstable_by_key(|(k, _)| **k);
+            for ((p1, p2), front) in pairs {
+                if p1 == p2 || front.len() < 2 {
+                    continue;
+                }
+                for (alpha, beta, rhs) in staircase_cuts(&self.disjunctions, front) {
+                    problem.add_row(
+                        (rhs as f64)..,
+                        [
+                            (partition_cost[*p1 as usize], alpha as f64),
+                            (partition_cost[*p2 as usize], beta as f64),
+                        ],
+                    );
+                    self.stats.n_cuts += 1;
+                }
+            }
+        }
+
         let mut model = problem.optimise(highs::Sense::Minimise);
         model.set_option("output_flag", true);
+        self.stats.build_time += build_start.elapsed();
+
+        let highs_start = Instant::now();
         let solved = model.solve();
+        self.stats.highs_time += highs_start.elapsed();
+        self.stats.n_highs_calls += 1;
         assert_eq!(solved.status(), highs::HighsModelStatus::Optimal);
         let solution = solved.get_solution();
         let value = solution
@@ -173,6 +386,14 @@ impl WdgSolverBinaryMIP {
 
         trace!("HIGHS result {:?} value {}", solved.status(), value);
 
-        value
+        let choices = vars
+            .iter()
+            .zip(solution.columns().iter().skip(partition_cost.len()))
+            .map(|(d_idx, x)| {
+                let (id, swapped) = self.disjunction_ids[*d_idx];
+                (id, if swapped { 1.0 - x } else { *x })
+            })
+            .collect();
+        WdgSolution { value, choices }
     }
 }
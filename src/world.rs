@@ -1,13 +1,20 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
 use log::{debug, trace};
 use tinyvec::TinyVec;
 
 use crate::{
     bnb::SolverSettings,
+    colgen::{ColGenStats, ColumnGenerationBound},
+    distances::DistanceMatrix,
+    history::BranchingHistory,
     longestpaths::LongestPaths,
+    unionfind::TrailUnionFind,
     problem::{DisjunctiveGraph, Edge},
-    wdg::{WdgEdge, WdgSolverBinaryMIP},
+    wdg::{WdgEdge, WdgSolution, WdgSolverBinaryMIP, WdgStats},
 };
 
 #[derive(Default, Debug)]
@@ -18,13 +25,46 @@ pub struct State {
 
 type PartitionId = u32;
 
+/// Branching score: the strong-branching or chronology score, then the
+/// conflict count. Strong-branching scores are weighted by the conflict
+/// count once the history is in use, see `BranchingHistory::conflict_weighted`.
+type BranchingKey = (i32, u32);
+
+/// How many of the disjunctions ranked by pseudo-costs are probed in each
+/// node, see `World::pseudo_cost_reliability`.
+const PSEUDO_COST_LOOKAHEAD: usize = 4;
+
 pub struct World {
     schedule: LongestPaths,
     nonunit_disjunctions: Vec<TinyVec<[Edge; 2]>>,
+    /// For each alternative of each disjunction, see `implied_edges`.
+    implied_edges: Vec<Vec<Option<Vec<Edge>>>>,
 
     n_partitions: usize,
     partitions: Vec<PartitionId>,
+    /// Joins the partitions connected by the edges pushed so far, so that
+    /// the WDG bound treats them as one.
+    partition_uf: TrailUnionFind,
     wdg_solver: WdgSolverBinaryMIP,
+    colgen: ColumnGenerationBound,
+
+    /// Time spent in `push` and `pop`.
+    pub time_propagation: Duration,
+    /// Time spent in strong branching probes.
+    pub time_probing: Duration,
+
+    history: BranchingHistory,
+    /// When non-zero and neither the WDG nor the column generation bound
+    /// needs the probes, disjunctions whose alternatives all have this many
+    /// observations in the history are ranked by their pseudo-costs instead
+    /// of being probed. Only the `PSEUDO_COST_LOOKAHEAD` best of them are
+    /// probed, and compete with the rest by their probes. Forced edges and
+    /// infeasible alternatives among the others are only found when their
+    /// children are created.
+    pub pseudo_cost_reliability: u32,
+    /// Update the history in `mk_state` and `record_branching`. Only needed
+    /// with restarts or pseudo-costs, as it slows down the probing loop.
+    pub record_history: bool,
 }
 
 impl World {
@@ -33,11 +73,18 @@ impl World {
         for node in problem.nodes.iter() {
             schedule.add_node(node);
         }
+        for (idx, objective) in problem.objectives.iter().enumerate() {
+            for cost in objective.costs.iter() {
+                schedule.add_objective_cost(idx + 1, cost);
+            }
+        }
 
         let mut nonunit_disjunctions: Vec<TinyVec<[Edge; 2]>> = Default::default();
         let mut partitioning_uf = petgraph::unionfind::UnionFind::new(problem.nodes.len());
 
         // Add fixed edges
+        let mut fixed = Vec::new();
+        let mut disjunctions = Vec::new();
         for edge in problem.edge_sets.iter() {
             match edge.as_slice() {
                 [] => return None,
@@ -46,13 +93,50 @@ impl World {
                         return None;
                     }
                     partitioning_uf.union(x.src, x.tgt);
+                    fixed.push(*x);
                 }
-                xs => {
-                    nonunit_disjunctions.push(xs.iter().copied().collect());
+                _ => disjunctions.push(edge),
+            }
+        }
+
+        // Drop disjunctions implied by the fixed edges and alternatives that
+        // would close a positive cycle, so that they are never probed. A
+        // disjunction left with one alternative becomes a fixed edge.
+        let distances = DistanceMatrix::new(
+            problem.nodes.len(),
+            &fixed,
+            disjunctions
+                .iter()
+                .flat_map(|es| es.iter().flat_map(|e| [e.src, e.tgt])),
+        );
+        debug!("distance matrix with {} entries", distances.n_entries());
+        for es in disjunctions {
+            if es.iter().any(|e| distances.implies(*e)) {
+                continue;
+            }
+            let es = es
+                .iter()
+                .copied()
+                .filter(|e| !distances.closes_positive_cycle(*e))
+                .collect::<TinyVec<[Edge; 2]>>();
+            match es.as_slice() {
+                [] => return None,
+                [x] => {
+                    if !schedule.add_fixed_edge(*x) {
+                        return None;
+                    }
+                    partitioning_uf.union(x.src, x.tgt);
                 }
+                _ => nonunit_disjunctions.push(es),
             }
         }
 
+        let implied_edges = implied_edges(&nonunit_disjunctions, &distances);
+        debug!(
+            "{} implied edges",
+            implied_edges.iter().flatten().flatten().map(|i| i.len()).sum::<usize>()
+        );
+
         let mut partitioning_representatives: HashMap<u32, usize> = Default::default();
         let mut n_partitions = 0;
         let mut partitions = Vec::new();
@@ -69,32 +153,89 @@ impl World {
 
         Some(Self {
             schedule,
-            nonunit_disjunctions,
             n_partitions,
             partitions,
+            partition_uf: TrailUnionFind::new(n_partitions),
             wdg_solver: WdgSolverBinaryMIP::default(),
+            colgen: ColumnGenerationBound::default(),
+            time_propagation: Duration::ZERO,
+            time_probing: Duration::ZERO,
+            history: BranchingHistory::new(nonunit_disjunctions.len()),
+            pseudo_cost_reliability: 0,
+            record_history: false,
+            nonunit_disjunctions,
+            implied_edges,
         })
     }
 
+    /// Number of disjunctions with more than two alternatives, which the WDG
+    /// and column generation bounds leave out unless probing rules out all
+    /// but two of their alternatives.
+    pub fn n_multiway_disjunctions(&self) -> usize {
+        self.nonunit_disjunctions.iter().filter(|es| es.len() > 2).count()
+    }
+
+    /// See `LongestPaths::set_objectives`. Must be called before branching.
+    pub fn set_objectives(&mut self, weights: &[u32], limits: &[Option<i32>]) -> bool {
+        self.schedule.set_objectives(weights, limits)
+    }
+
     pub fn longestpaths_bound(&self) -> i32 {
         self.schedule.objective_value
     }
 
+    /// The current earliest time of every node.
+    pub fn positions(&self) -> Vec<i32> {
+        self.schedule.nodes.iter().map(|n| n.position).collect()
+    }
+
+    /// Record the bound increase observed when branching on `edge`.
+    pub fn record_branching(&mut self, edge: Edge, lb_increase: i32) {
+        if self.record_history {
+            self.history.record(edge, lb_increase);
+        }
+    }
+
+    pub fn wdg_stats(&self) -> WdgStats {
+        self.wdg_solver.stats
+    }
+
+    pub fn colgen_stats(&self) -> ColGenStats {
+        self.colgen.stats
+    }
+
+    /// Number of `push_edge` calls so far, including strong branching probes.
+    pub fn n_push_edge(&self) -> u64 {
+        self.schedule.n_push_edge
+    }
+
+    /// Deterministic work counter, see `SolverSettings::work_limit`.
+    pub fn work_units(&self) -> u64 {
+        self.schedule.n_edges_scanned
+    }
+
     pub fn mk_state(
         &mut self,
         settings: &SolverSettings,
         pre_lb: i32,
         cost_ub: i32,
     ) -> Option<State> {
-        let mut branching: Option<(i32, TinyVec<[Edge; 2]>)> = None;
+        // Branch on the disjunction with the highest score, weighted and
+        // tie-broken by conflict count once the history is in use.
+        let mut branching: Option<(BranchingKey, TinyVec<[Edge; 2]>)> = None;
         let mut lb: Option<i32> = None;
+        // The alternatives and bound increases of the disjunctions in the WDG model.
+        let mut wdg_alternatives: Vec<[(Edge, i32); 2]> = Vec::new();
+        // Disjunctions ranked by their pseudo-costs instead of probes.
+        let mut lookahead: Vec<(BranchingKey, usize)> = Vec::new();
         self.wdg_solver.clear();
+        self.colgen.clear();
         let realized_cost = self.schedule.objective_value;
         debug!("realized cost {}", realized_cost);
         debug!("MK STATE");
 
         // Strong branching + gather conflict bounding problem coefficients
-        for es in self.nonunit_disjunctions.iter() {
+        for (d_idx, es) in self.nonunit_disjunctions.iter().enumerate() {
             // Skip disjunctions that are satisfied by the relaxed schedule
             if es.iter().any(|e| {
                 let t1 = self.schedule.nodes[e.src as usize].position;
@@ -104,19 +245,50 @@ impl World {
                 // trace!("skipping satsified constraint {:?}", es);
                 continue;
             }
+            let conflicts = if self.record_history {
+                self.history.add_conflict(d_idx);
+                self.history.conflicts(d_idx)
+            } else {
+                0
+            };
+
+            // Reliability branching: once every alternative that can be in a
+            // solution has enough observations, and no bound needs the
+            // probes, the disjunction is ranked by its pseudo-costs instead.
+            if settings.use_strong_branching
+                && self.pseudo_cost_reliability > 0
+                && !settings.use_wdg_bound
+                && !settings.use_colgen_bound
+            {
+                let estimates = es
+                    .iter()
+                    .enumerate()
+                    .filter(|(alt_idx, _)| self.implied_edges[d_idx][*alt_idx].is_some())
+                    .map(|(_, e)| self.history.estimate(*e, self.pseudo_cost_reliability))
+                    .collect::<Option<TinyVec<[i32; 2]>>>();
+                if let Some(estimates) = estimates.filter(|x| !x.is_empty()) {
+                    lookahead.push(((branching_score(&estimates), conflicts), d_idx));
+                    continue;
+                }
+            }
 
             let mut valid_edges: TinyVec<[(Edge, i32); 2]> = Default::default();
             let mut route_contraction_constraints: TinyVec<[TinyVec<[WdgEdge; 8]>; 2]> =
                 Default::default();
 
-            for e in es.iter() {
+            for (alt_idx, e) in es.iter().enumerate() {
+                // Alternatives that are in no solution are dropped unprobed.
+                let Some(implied) = self.implied_edges[d_idx][alt_idx].as_ref() else {
+                    continue;
+                };
                 let mut total_bound_change = 0;
                 let mut constraints: TinyVec<[WdgEdge; 8]> = Default::default();
 
                 // debug!("testing edge {:?}", e);
-                let schedule_feasible = self.schedule.hypothetical_edge_lb(*e, |node, d_cost| {
+                let probe_start = Instant::now();
+                let schedule_feasible = self.schedule.hypothetical_edge_lb(*e, implied, |node, d_cost| {
                     // debug!("bound change {} {}", self.partitions[node as usize], d_cost);
-                    let partition = self.partitions[node as usize];
+                    let partition = self.partition_uf.find(self.partitions[node as usize]);
 
                     let c_i = constraints
                         .iter_mut()
@@ -132,6 +304,7 @@ impl World {
                     constraints[c_i].d_cost += d_cost;
                     total_bound_change += d_cost;
                 });
+                self.time_probing += probe_start.elapsed();
 
                 let ub_feasible = realized_cost + total_bound_change < cost_ub;
                 if schedule_feasible && ub_feasible {
@@ -143,32 +316,41 @@ impl World {
             // Short-circuit when there is a forced edge or infeasibility.
             if valid_edges.len() < 2 {
                 lb = Some(pre_lb);
-                branching = Some((i32::MAX, valid_edges.into_iter().map(|x| x.0).collect()));
+                branching = Some(((i32::MAX, 0), valid_edges.into_iter().map(|x| x.0).collect()));
                 break;
             }
 
-            if settings.use_wdg_bound {
-                // The test problems all have 2-way disjunctions. The
-                // WDG solver needs to be generalized to handle disjunctions
-                // with 3 or more alternatives.
-                assert!(valid_edges.len() == 2 && route_contraction_constraints.len() == 2);
-
-                if !route_contraction_constraints[0].is_empty()
-                    && !route_contraction_constraints[1].is_empty()
-                {
-                    self.wdg_solver.add_disjunction(
-                        &route_contraction_constraints[0],
-                        &route_contraction_constraints[1],
-                    );
-                }
+            // The WDG solver only handles 2-way disjunctions. Disjunctions with
+            // 3 or more alternatives (such as station capacity constraints) are
+            // left out of the bound, which keeps it valid but weaker.
+            if settings.use_wdg_bound
+                && valid_edges.len() == 2
+                && !route_contraction_constraints[0].is_empty()
+                && !route_contraction_constraints[1].is_empty()
+            {
+                self.wdg_solver.add_disjunction(
+                    wdg_alternatives.len(),
+                    &route_contraction_constraints[0],
+                    &route_contraction_constraints[1],
+                );
+                wdg_alternatives.push([valid_edges[0], valid_edges[1]]);
+            }
+            if settings.use_colgen_bound
+                && valid_edges.len() == 2
+                && !(route_contraction_constraints[0].is_empty()
+                    && route_contraction_constraints[1].is_empty())
+            {
+                self.colgen.add_disjunction(
+                    [valid_edges[0].0, valid_edges[1].0],
+                    &route_contraction_constraints[0],
+                    &route_contraction_constraints[1],
+                );
             }
 
             // Compute the strong-branching or chronology score.
             let score = if settings.use_strong_branching {
-                let min_lb_incr = valid_edges.iter().map(|(_, d_lb)| d_lb).min().unwrap();
-                let max_lb_incr = valid_edges.iter().map(|(_, d_lb)| d_lb).max().unwrap();
-
-                5 * min_lb_incr + max_lb_incr
+                let lb_incrs: TinyVec<[i32; 2]> = valid_edges.iter().map(|(_, d_lb)| *d_lb).collect();
+                self.history.conflict_weighted(d_idx, branching_score(&lb_incrs))
             } else {
                 let earliest_time = es
                     .iter()
@@ -183,12 +365,66 @@ impl World {
                 -earliest_time
             };
 
-            if score > branching.as_ref().map(|(s, _)| *s).unwrap_or(i32::MIN) {
-                branching = Some((score, valid_edges.into_iter().map(|(e, _)| e).collect()));
+            let key = (score, conflicts);
+            if Some(key) > branching.as_ref().map(|(k, _)| *k) {
+                branching = Some((key, valid_edges.into_iter().map(|(e, _)| e).collect()));
+            }
+        }
+
+        // Probe the disjunctions with the best pseudo-cost scores, so that
+        // they compete with the others by their actual bound increases.
+        lookahead.sort_by_key(|(key, _)| std::cmp::Reverse(*key));
+        for ((_, conflicts), d_idx) in lookahead.into_iter().take(PSEUDO_COST_LOOKAHEAD) {
+            if lb.is_some() {
+                break;
+            }
+            let mut valid_edges: TinyVec<[(Edge, i32); 2]> = Default::default();
+            for (alt_idx, e) in self.nonunit_disjunctions[d_idx].iter().enumerate() {
+                let Some(implied) = self.implied_edges[d_idx][alt_idx].as_ref() else {
+                    continue;
+                };
+                let mut total_bound_change = 0;
+                let probe_start = Instant::now();
+                let schedule_feasible = self
+                    .schedule
+                    .hypothetical_edge_lb(*e, implied, |_, d_cost| total_bound_change += d_cost);
+                self.time_probing += probe_start.elapsed();
+                if schedule_feasible && realized_cost + total_bound_change < cost_ub {
+                    valid_edges.push((*e, total_bound_change));
+                }
+            }
+            if valid_edges.len() < 2 {
+                lb = Some(pre_lb);
+                branching = Some(((i32::MAX, 0), valid_edges.into_iter().map(|x| x.0).collect()));
+                break;
+            }
+            let lb_incrs: TinyVec<[i32; 2]> = valid_edges.iter().map(|(_, d_lb)| *d_lb).collect();
+            let key = (self.history.conflict_weighted(d_idx, branching_score(&lb_incrs)), conflicts);
+            if Some(key) > branching.as_ref().map(|(k, _)| *k) {
+                branching = Some((key, valid_edges.into_iter().map(|(e, _)| e).collect()));
             }
         }
 
-        let lb = lb.unwrap_or_else(|| realized_cost + self.wdg_solver.solve(self.n_partitions, settings.use_relaxed_wdg));
+        let lb = match lb {
+            Some(lb) => lb,
+            None => {
+                let solution = self.wdg_solver.solve(self.n_partitions, settings.use_relaxed_wdg);
+                if settings.use_wdg_branching {
+                    if let Some(edges) = wdg_branching(&solution, &wdg_alternatives) {
+                        branching = Some(((0, 0), edges));
+                    }
+                }
+                let mut bound = solution.value;
+                if settings.use_colgen_bound {
+                    let (partitions, partition_uf) = (&self.partitions, &self.partition_uf);
+                    let colgen_bound = self.colgen.solve(&mut self.schedule, |node| {
+                        partition_uf.find(partitions[node as usize])
+                    });
+                    bound = bound.max(colgen_bound);
+                }
+                realized_cost.saturating_add(bound)
+            }
+        };
         if lb >= cost_ub {
             return None;
         }
@@ -201,11 +437,135 @@ impl World {
         })
     }
 
+    /// Check a complete solution given as one chosen edge per disjunction.
+    /// Returns its objective value and node positions if the edges are
+    /// feasible and satisfy every disjunction. The world is left unchanged.
+    pub fn try_solution(&mut self, edges: &[Edge]) -> Option<(i32, Vec<i32>)> {
+        let mut n_pushed = 0;
+        let mut feasible = true;
+        for e in edges.iter() {
+            if !self.push(*e) {
+                feasible = false;
+                break;
+            }
+            n_pushed += 1;
+        }
+
+        let complete = feasible
+            && self.nonunit_disjunctions.iter().all(|es| {
+                es.iter().any(|e| {
+                    let t1 = self.schedule.nodes[e.src as usize].position;
+                    let t2 = self.schedule.nodes[e.tgt as usize].position;
+                    t1 + e.weight <= t2
+                })
+            });
+        let result = complete.then(|| (self.schedule.objective_value, self.positions()));
+
+        for _ in 0..n_pushed {
+            self.pop();
+        }
+        result
+    }
+
     pub fn push(&mut self, e: Edge) -> bool {
-        self.schedule.push_edge(e, |_, _| {})
+        let start = Instant::now();
+        let ok = self.schedule.push_edge(e, |_, _| {});
+        if ok {
+            self.partition_uf.union(
+                self.partitions[e.src as usize],
+                self.partitions[e.tgt as usize],
+            );
+        }
+        self.time_propagation += start.elapsed();
+        ok
     }
 
     pub fn pop(&mut self) {
+        let start = Instant::now();
         self.schedule.pop(|_| {});
+        self.partition_uf.undo();
+        self.time_propagation += start.elapsed();
     }
 }
+
+/// For each alternative of each disjunction, the alternatives of other
+/// two-way disjunctions that every solution containing it also chooses,
+/// because the other alternative would close a positive cycle with it
+/// through the fixed edges. Probing with them gives a larger bound increase
+/// than the alternative alone. `None` if the alternative leaves another
+/// disjunction without any alternative, so that it is in no solution.
+///
+/// Disjunctions with more alternatives, such as station capacities, share
+/// their nodes with many others and rarely have all but one excluded, so
+/// they are not considered as targets.
+fn implied_edges(
+    disjunctions: &[TinyVec<[Edge; 2]>],
+    distances: &DistanceMatrix,
+) -> Vec<Vec<Option<Vec<Edge>>>> {
+    let mut by_src: HashMap<u32, Vec<(usize, usize)>> = HashMap::new();
+    for (d_idx, es) in disjunctions.iter().enumerate().filter(|(_, es)| es.len() == 2) {
+        for (alt_idx, e) in es.iter().enumerate() {
+            by_src.entry(e.src).or_default().push((d_idx, alt_idx));
+        }
+    }
+
+    let mut excluded = Vec::new();
+    let mut implied_for = |d_idx: usize, e: Edge| {
+        excluded.clear();
+        for (node, _) in distances.reachable(e.tgt) {
+            for &(other, alt_idx) in by_src.get(node).into_iter().flatten() {
+                if other != d_idx && distances.excludes(e, disjunctions[other][alt_idx]) {
+                    excluded.push((other, alt_idx));
+                }
+            }
+        }
+        excluded.sort_unstable();
+        let mut implied = Vec::new();
+        for group in excluded.chunk_by(|a, b| a.0 == b.0) {
+            let es = &disjunctions[group[0].0];
+            match es.len() - group.len() {
+                0 => return None,
+                1 => {
+                    let remaining = (0..es.len()).find(|i| group.iter().all(|g| g.1 != *i));
+                    implied.push(es[remaining.unwrap()]);
+                }
+                _ => {}
+            }
+        }
+        Some(implied)
+    };
+
+    disjunctions
+        .iter()
+        .enumerate()
+        .map(|(d_idx, es)| es.iter().map(|e| implied_for(d_idx, *e)).collect())
+        .collect()
+}
+
+/// The strong-branching score of a disjunction from the bound increases of
+/// its alternatives.
+fn branching_score(lb_incrs: &[i32]) -> i32 {
+    5 * lb_incrs.iter().min().unwrap() + lb_incrs.iter().max().unwrap()
+}
+
+/// The disjunction to branch on according to the WDG solution: a fractional
+/// one if there is any, otherwise the one whose chosen alternative increases
+/// the bound the most. Its alternatives are ordered with the one preferred
+/// by the solution first, which the searches keep between children with
+/// equal bounds.
+fn wdg_branching(
+    solution: &WdgSolution,
+    alternatives: &[[(Edge, i32); 2]],
+) -> Option<TinyVec<[Edge; 2]>> {
+    let (_, id, x) = solution
+        .choices
+        .iter()
+        .map(|(id, x)| {
+            let fractional = *x > 1e-6 && *x < 1.0 - 1e-6;
+            let chosen = if *x >= 0.5 { 0 } else { 1 };
+            ((fractional, alternatives[*id][chosen].1), *id, *x)
+        })
+        .max_by_key(|(key, _, _)| *key)?;
+    let [(e1, _), (e2, _)] = alternatives[id];
+    Some(if x >= 0.5 { [e1, e2] } else { [e2, e1] }.into_iter().collect())
+}